@@ -1,4 +1,23 @@
 pub mod account;
 pub mod block;
+pub mod block_tree;
 pub mod blockchain;
+pub mod clock;
+pub mod disk_store;
+pub mod encoding;
+pub mod index;
+pub mod mempool;
+pub mod merkle;
+pub mod miner;
+pub mod orphans;
+pub mod params;
+pub mod retarget;
+pub mod snapshot;
+pub mod state;
+pub mod state_tree;
+pub mod storage;
+pub mod store;
+pub mod subsidy;
+pub mod target;
 pub mod transaction;
+pub mod undo;
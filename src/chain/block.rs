@@ -1,66 +1,173 @@
-// Yes, I acknowledge the merkle root isn't actually the merkle root right now.
-
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
+use crate::chain::encoding::{self, EncodingError};
+use crate::chain::merkle::{self, MerkleProof};
+use crate::chain::params::ChainParams;
+use crate::chain::state::AccountState;
+use crate::chain::state_tree::StateTree;
+use crate::chain::target;
 use crate::chain::transaction::Transaction;
 
+// Everything in a block except its transactions. The hash of a block is the
+// hash of its header.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub index: u64,
+    pub timestamp: u64,
+    pub prev_hash: [u8; 32],
+    pub merkle_root: [u8; 32],
+    // Root of the account state tree after the block's transactions
+    pub state_root: [u8; 32],
+    pub bits: u32,
+    pub nonce: u64,
+}
+
+impl BlockHeader {
+    // The header hash commits to the transactions through the merkle root
+    // and to the resulting account state through the state root
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.index.to_le_bytes());
+        hasher.update(self.timestamp.to_le_bytes());
+        hasher.update(self.prev_hash);
+        hasher.update(self.merkle_root);
+        hasher.update(self.state_root);
+        hasher.update(self.bits.to_le_bytes());
+        hasher.update(self.nonce.to_le_bytes());
+        hasher.finalize().into()
+    }
+
+    // Whether the header hash is at or below the target it claims
+    pub fn meets_target(&self) -> bool {
+        target::hash_meets_target(&self.hash(), self.bits)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        encoding::encode(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, EncodingError> {
+        encoding::decode(bytes)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Block {
     pub index: u64,
     pub timestamp: u64,
     pub prev_hash: [u8; 32],
     pub merkle_root: [u8; 32],
+    pub state_root: [u8; 32],
+    pub bits: u32,
     pub nonce: u64,
     pub data: Vec<Transaction>,
 }
 
 impl Block {
-    pub fn new(index: u64, prev_hash: [u8; 32], data: [u8; 1024]) -> Self {
+    // The state root is left zero for the caller to fill in, since it
+    // depends on the state the block is applied to
+    pub fn new(index: u64, prev_hash: [u8; 32], bits: u32, data: Vec<Transaction>) -> Self {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
 
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        let merkle_root = hasher.finalize().into();
+        let merkle_root = Self::calculate_merkle_root(&data);
 
         Self {
             index,
             timestamp,
             prev_hash,
             merkle_root,
+            state_root: [0; 32],
+            bits,
             nonce: 0,
-            data: Vec::new(),
+            data,
         }
     }
 
-    pub fn create_genesis() -> Self {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    // Build the first block of a chain. Everything comes from the params so
+    // every node derives the same genesis, with the premine paid out by
+    // coinbase transactions.
+    pub fn create_genesis(params: &ChainParams) -> Self {
+        let data: Vec<Transaction> = params
+            .premine
+            .iter()
+            .map(|(recipient, amount)| Transaction::coinbase(*recipient, *amount, 0))
+            .collect();
+
+        let mut premine: HashMap<[u8; 33], AccountState> = HashMap::new();
+        for (recipient, amount) in &params.premine {
+            let state = premine.entry(*recipient).or_default();
+            state.balance = state.balance.saturating_add(*amount);
+        }
 
         Self {
             index: 0,
-            timestamp,
+            timestamp: params.genesis_timestamp,
             prev_hash: [0; 32],
-            merkle_root: [0; 32],
-            nonce: 0,
-            data: Vec::new(),
+            merkle_root: Self::calculate_merkle_root(&data),
+            state_root: StateTree::new(premine).root(),
+            bits: params.initial_bits,
+            nonce: params.genesis_nonce,
+            data,
+        }
+    }
+
+    // Build the merkle tree over the transaction ids and return its root
+    pub fn calculate_merkle_root(data: &[Transaction]) -> [u8; 32] {
+        let leaves: Vec<[u8; 32]> = data.iter().map(|tx| tx.txid()).collect();
+        merkle::merkle_root(&leaves)
+    }
+
+    // Prove that the transaction at `index` is committed to by the merkle root
+    pub fn merkle_proof(&self, index: usize) -> Option<MerkleProof> {
+        let leaves: Vec<[u8; 32]> = self.data.iter().map(|tx| tx.txid()).collect();
+        MerkleProof::generate(&leaves, index)
+    }
+
+    // Check that the body is the one committed to by the header. Duplicate
+    // transactions are rejected since the tree duplicates the last node on
+    // odd levels, which would let a mutated body keep the same root.
+    pub fn verify_merkle_root(&self) -> bool {
+        let mut seen = HashSet::new();
+        if !self.data.iter().all(|tx| seen.insert(tx.txid())) {
+            return false;
+        }
+
+        self.merkle_root == Self::calculate_merkle_root(&self.data)
+    }
+
+    // Canonical versioned encoding of the header and body
+    pub fn encode(&self) -> Vec<u8> {
+        encoding::encode(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, EncodingError> {
+        encoding::decode(bytes)
+    }
+
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            index: self.index,
+            timestamp: self.timestamp,
+            prev_hash: self.prev_hash,
+            merkle_root: self.merkle_root,
+            state_root: self.state_root,
+            bits: self.bits,
+            nonce: self.nonce,
         }
     }
 
     pub fn hash(&self) -> [u8; 32] {
-        let mut hasher = Sha256::new();
-        hasher.update(self.index.to_le_bytes());
-        hasher.update(self.timestamp.to_le_bytes());
-        hasher.update(self.prev_hash);
-        hasher.update(self.merkle_root);
-        hasher.update(self.nonce.to_le_bytes());
-        // hasher.update(self.data);
-        let hash: [u8; 32] = hasher.finalize().into();
-        return hash;
+        self.header().hash()
+    }
+
+    pub fn meets_target(&self) -> bool {
+        self.header().meets_target()
     }
 }
 
@@ -70,6 +177,8 @@ impl fmt::Display for Block {
         writeln!(f, "  Timestamp:        {}", self.timestamp)?;
         writeln!(f, "  Prev Hash:        {}", hex::encode(self.prev_hash))?;
         writeln!(f, "  Merkle Root:      {}", hex::encode(self.merkle_root))?;
+        writeln!(f, "  State Root:       {}", hex::encode(self.state_root))?;
+        writeln!(f, "  Bits:             {:08x}", self.bits)?;
         writeln!(f, "  Nonce:            {}", self.nonce)?;
         writeln!(f, "  Num Transactions: {}", self.data.len())
     }
@@ -0,0 +1,122 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::chain::block::BlockHeader;
+use crate::chain::target::{self, U256};
+
+// A block header along with where it sits in the tree
+pub struct BlockNode {
+    pub hash: [u8; 32],
+    pub header: BlockHeader,
+    pub height: u64,
+    // Total work of this block and all of its ancestors
+    pub cumulative_work: U256,
+    // Set once the block or one of its ancestors failed to connect, so
+    // nothing built on it is accepted
+    pub invalid: bool,
+}
+
+// Header of every known block indexed by hash, including side branches
+pub struct BlockTree {
+    nodes: HashMap<[u8; 32], BlockNode>,
+    tips: HashSet<[u8; 32]>,
+    children: HashMap<[u8; 32], Vec<[u8; 32]>>,
+}
+
+impl BlockTree {
+    pub fn new(genesis: BlockHeader) -> Self {
+        let hash = genesis.hash();
+        let node = BlockNode {
+            hash,
+            cumulative_work: target::block_work(genesis.bits),
+            height: 0,
+            invalid: false,
+            header: genesis,
+        };
+
+        Self {
+            nodes: HashMap::from([(hash, node)]),
+            tips: HashSet::from([hash]),
+            children: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.nodes.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &[u8; 32]) -> Option<&BlockNode> {
+        self.nodes.get(hash)
+    }
+
+    // Hashes of blocks with no known children, one per branch
+    pub fn tips(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.tips.iter()
+    }
+
+    // Insert a header whose parent is already in the tree. A child of an
+    // invalid block is invalid too.
+    pub fn insert(&mut self, header: BlockHeader) -> &BlockNode {
+        let hash = header.hash();
+        if self.nodes.contains_key(&hash) {
+            return &self.nodes[&hash];
+        }
+        let parent = &self.nodes[&header.prev_hash];
+
+        let node = BlockNode {
+            hash,
+            height: parent.height + 1,
+            cumulative_work: parent
+                .cumulative_work
+                .saturating_add(target::block_work(header.bits)),
+            invalid: parent.invalid,
+            header,
+        };
+
+        self.tips.remove(&node.header.prev_hash);
+        self.tips.insert(hash);
+        self.children
+            .entry(node.header.prev_hash)
+            .or_default()
+            .push(hash);
+        self.nodes.entry(hash).or_insert(node)
+    }
+
+    // Flag a block and every block built on it as invalid
+    pub fn mark_invalid(&mut self, hash: &[u8; 32]) {
+        let mut pending = vec![*hash];
+
+        while let Some(hash) = pending.pop() {
+            if let Some(node) = self.nodes.get_mut(&hash) {
+                node.invalid = true;
+            }
+            if let Some(children) = self.children.get(&hash) {
+                pending.extend(children);
+            }
+        }
+    }
+
+    // Hashes from genesis up to and including `hash`
+    pub fn path_to(&self, hash: [u8; 32]) -> Vec<[u8; 32]> {
+        let mut path = Vec::new();
+        let mut current = self.nodes.get(&hash);
+
+        while let Some(node) = current {
+            path.push(node.hash);
+            if node.height == 0 {
+                break;
+            }
+            current = self.nodes.get(&node.header.prev_hash);
+        }
+
+        path.reverse();
+        path
+    }
+}
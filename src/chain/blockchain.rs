@@ -1,71 +1,1551 @@
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
 
-use crate::{chain::block::Block, chain::transaction::Transaction};
+use crate::chain::block::{Block, BlockHeader};
+use crate::chain::block_tree::BlockTree;
+use crate::chain::clock::{Clock, SystemClock};
+use crate::chain::disk_store::DiskStore;
+use crate::chain::encoding::{self, EncodingError};
+use crate::chain::index::{self, INDEXES, IndexOptions, TxLocation};
+use crate::chain::mempool::Mempool;
+use crate::chain::miner::Miner;
+use crate::chain::orphans::OrphanPool;
+use crate::chain::params::ChainParams;
+use crate::chain::snapshot::{SNAPSHOT, Snapshot, SnapshotError};
+use crate::chain::state::AccountState;
+use crate::chain::state_tree::{AccountProof, StateTree};
+use crate::chain::storage::StorageError;
+use crate::chain::store::{
+    BEST_TIP, ChainStore, INVALID, MemoryStore, PRUNE_DEPTH, PRUNED_HEIGHT, WriteBatch, WriteOp,
+};
+use crate::chain::target::U256;
+use crate::chain::transaction::{Transaction, TransactionError};
+use crate::chain::undo::BlockUndo;
+
+// How far ahead of an account's next nonce the mempool will queue transactions
+pub const MAX_NONCE_GAP: u64 = 64;
+
+// Default limit on the encoded size of a block
+pub const MAX_BLOCK_SIZE: usize = 1_000_000;
+
+// Number of previous blocks whose median timestamp a new block must exceed
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+// Default limit on how far ahead of the local clock a block may be stamped
+pub const MAX_FUTURE_DRIFT: u64 = 2 * 60 * 60;
 
 pub struct Blockchain {
-    pub chain: Vec<Block>,
-    pub mempool: Vec<Transaction>,
-    pub balances: HashMap<[u8; 33], i64>,
-    pub difficulty: usize,
+    // Headers of every known block, including side branches, rebuilt from
+    // the store on startup
+    pub tree: BlockTree,
+    // Hashes of the active chain from genesis to the tip, which is always
+    // the valid branch with the most cumulative work
+    pub chain: Vec<[u8; 32]>,
+    // Block bodies, account state, undo data for the active chain and the
+    // best tip
+    pub store: Box<dyn ChainStore>,
+    // Blocks waiting for a parent that hasn't arrived yet
+    pub orphans: OrphanPool,
+    pub mempool: Mempool,
+    pub params: ChainParams,
+    pub max_block_size: usize,
+    pub max_future_drift: u64,
+    pub clock: Box<dyn Clock>,
+    pub miner: Miner,
+    // Lookup indexes kept up to date as blocks are connected and
+    // disconnected. Changed through `set_indexes` so they get backfilled.
+    indexes: IndexOptions,
+    // Height of the snapshot the chain was bootstrapped from, or 0. Blocks
+    // at or below it have no body or undo data, so the chain can't
+    // reorganize below it.
+    base_height: u64,
+    // Number of most recent blocks whose bodies and undo data are kept, or
+    // None to keep everything
+    prune_depth: Option<u64>,
+    // Height up to which bodies and undo data have been deleted, or 0
+    pruned_height: u64,
+    // Account state hashed into the tree whose root blocks commit to,
+    // matching the state at the tip
+    state_tree: StateTree,
+}
+
+// Account state after applying a list of transactions, for the accounts
+// they touched
+#[derive(Default)]
+pub struct StateUpdate {
+    pub accounts: HashMap<[u8; 33], AccountState>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BlockError {
+    AlreadyKnown,
+    UnknownParent,
+    InvalidParent,
+    InvalidGenesis,
+    InvalidPrevHash,
+    InvalidIndex { expected: u64, found: u64 },
+    InvalidBits { expected: u32, found: u32 },
+    InsufficientWork,
+    InvalidMerkleRoot,
+    InvalidTransaction(usize, TransactionError),
+    MissingCoinbase,
+    InvalidCoinbaseHeight { expected: u64, found: u64 },
+    CoinbaseTooLarge { allowed: u64, found: u64 },
+    BlockTooLarge { max: usize, found: usize },
+    Storage(String),
+    TimestampTooOld { median: u64, found: u64 },
+    TimestampTooFarAhead { max: u64, found: u64 },
+    ReorgTooDeep { fork: u64, min: u64 },
+    InvalidStateRoot,
+}
+
+// Why a chain failed a full check from genesis
+#[derive(Debug, PartialEq)]
+pub enum ChainError {
+    Empty,
+    InvalidBlock { height: u64, error: BlockError },
+    StateMismatch,
+    Storage(String),
 }
 
 impl Blockchain {
-    pub fn new(difficulty: usize) -> Self {
-        let genesis = Block::create_genesis();
+    // Start an in-memory chain from the genesis block described by `params`
+    pub fn new(params: ChainParams) -> Self {
+        Self::with_store(params, Box::new(MemoryStore::default())).expect("memory store can't fail")
+    }
+
+    // Open the chain stored on disk in `dir`, creating it if needed
+    pub fn open(params: ChainParams, dir: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let store = DiskStore::open(dir, params.magic)?;
+        Self::with_store(params, Box::new(store))
+    }
+
+    // Load the chain kept in `store`. An empty store is given the genesis
+    // block with the premine credited; otherwise the block tree is rebuilt
+    // from the stored headers and the active chain from the best tip.
+    pub fn with_store(
+        params: ChainParams,
+        mut store: Box<dyn ChainStore>,
+    ) -> Result<Self, StorageError> {
+        let genesis = params.genesis();
+        let genesis_hash = genesis.hash();
+
+        if store.get_meta(BEST_TIP)?.is_none() {
+            let premine = Self::compute_update(|_| AccountState::default(), &genesis.data)
+                .expect("premine fits in a u64");
+
+            let mut batch = WriteBatch::default();
+            batch.push(WriteOp::PutBlock(genesis.clone()));
+            for (account, state) in premine.accounts {
+                batch.push(WriteOp::PutAccount(account, state));
+            }
+            batch.push(WriteOp::PutMeta(
+                BEST_TIP.to_string(),
+                genesis_hash.to_vec(),
+            ));
+            store.write(batch)?;
+        } else if store.get_header(&genesis_hash)?.is_none() {
+            return Err(StorageError::GenesisMismatch);
+        }
+
+        // Parents always have a lower index than their children
+        let mut tree = BlockTree::new(genesis.header());
+        let mut headers = store.headers()?;
+        headers.sort_by_key(|header| header.index);
+        for header in headers {
+            if tree.contains(&header.prev_hash) && !tree.contains(&header.hash()) {
+                tree.insert(header);
+            }
+        }
+        if let Some(bytes) = store.get_meta(INVALID)? {
+            for hash in encoding::decode::<Vec<[u8; 32]>>(&bytes)? {
+                tree.mark_invalid(&hash);
+            }
+        }
+
+        let best_tip: [u8; 32] = store
+            .get_meta(BEST_TIP)?
+            .and_then(|tip| tip.try_into().ok())
+            .filter(|tip| tree.contains(tip))
+            .ok_or(StorageError::UnknownTip)?;
+
+        let indexes = match store.get_meta(INDEXES)? {
+            Some(bytes) => encoding::decode(&bytes)?,
+            None => IndexOptions::default(),
+        };
+        let state_tree = StateTree::new(store.accounts()?);
+        let base_height = match store.get_meta(SNAPSHOT)? {
+            Some(bytes) => encoding::decode::<(u64, [u8; 32])>(&bytes)?.0,
+            None => 0,
+        };
+        let prune_depth = match store.get_meta(PRUNE_DEPTH)? {
+            Some(bytes) => encoding::decode(&bytes)?,
+            None => None,
+        };
+        let pruned_height = match store.get_meta(PRUNED_HEIGHT)? {
+            Some(bytes) => encoding::decode(&bytes)?,
+            None => 0,
+        };
+
+        Ok(Self {
+            chain: tree.path_to(best_tip),
+            tree,
+            store,
+            orphans: OrphanPool::default(),
+            mempool: Mempool::default(),
+            params,
+            max_block_size: MAX_BLOCK_SIZE,
+            max_future_drift: MAX_FUTURE_DRIFT,
+            clock: Box::new(SystemClock),
+            miner: Miner::default(),
+            indexes,
+            base_height,
+            prune_depth,
+            pruned_height,
+            state_tree,
+        })
+    }
+
+    // Start a chain from a snapshot and the headers from genesis up to the
+    // snapshot's block, without the blocks before it. The snapshot's hash
+    // must match `trusted` or, if the operator didn't supply one, the hash
+    // the chain parameters commit to for that height. A matching state root
+    // in the headers isn't enough on its own, since anyone can mine a short
+    // header chain over a made-up state.
+    pub fn from_snapshot(
+        params: ChainParams,
+        store: Box<dyn ChainStore>,
+        snapshot: &Snapshot,
+        headers: &[BlockHeader],
+        trusted: Option<[u8; 32]>,
+    ) -> Result<Self, SnapshotError> {
+        let expected = trusted
+            .or_else(|| {
+                params
+                    .snapshots
+                    .iter()
+                    .find(|(height, _)| *height == snapshot.height)
+                    .map(|(_, hash)| *hash)
+            })
+            .ok_or(SnapshotError::Untrusted)?;
+        let found = snapshot.hash();
+        if found != expected {
+            return Err(SnapshotError::HashMismatch { expected, found });
+        }
+
+        if store.get_meta(BEST_TIP)?.is_some() {
+            return Err(SnapshotError::NotEmpty);
+        }
+        if headers.len() as u64 != snapshot.height.saturating_add(1) {
+            return Err(SnapshotError::InvalidHeaders(headers.len()));
+        }
+        if headers.last().map(BlockHeader::hash) != Some(snapshot.block_hash) {
+            return Err(SnapshotError::BlockMismatch);
+        }
+
+        let state_tree = StateTree::new(snapshot.accounts());
+        if headers[headers.len() - 1].state_root != state_tree.root() {
+            return Err(SnapshotError::StateRootMismatch);
+        }
+
+        let mut blockchain = Self::with_store(params, store)?;
+        if headers[0] != *blockchain.tip_header() {
+            return Err(SnapshotError::InvalidHeader {
+                height: 0,
+                error: BlockError::InvalidGenesis,
+            });
+        }
+
+        let mut batch = WriteBatch::default();
+        for (height, pair) in (1..).zip(headers.windows(2)) {
+            let (parent, header) = (&pair[0], &pair[1]);
+            let invalid = |error| SnapshotError::InvalidHeader { height, error };
+
+            if header.prev_hash != parent.hash() {
+                return Err(invalid(BlockError::InvalidPrevHash));
+            }
+            blockchain
+                .check_header(header, &header.prev_hash)
+                .map_err(invalid)?;
+
+            blockchain.tree.insert(header.clone());
+            batch.push(WriteOp::PutHeader(header.clone()));
+        }
+
+        // Replace the premine with the snapshot's state
+        for (account, _) in blockchain.store.accounts()? {
+            batch.push(WriteOp::DeleteAccount(account));
+        }
+        for entry in &snapshot.entries {
+            batch.push(WriteOp::PutAccount(entry.account, entry.state));
+        }
+        batch.push(WriteOp::PutMeta(
+            BEST_TIP.to_string(),
+            snapshot.block_hash.to_vec(),
+        ));
+        batch.push(WriteOp::PutMeta(
+            SNAPSHOT.to_string(),
+            encoding::encode(&(snapshot.height, found)),
+        ));
+        blockchain.store.write(batch)?;
 
-        Self {
-            chain: vec![genesis],
-            mempool: Vec::new(),
-            balances: HashMap::new(),
-            difficulty,
+        blockchain.chain = blockchain.tree.path_to(snapshot.block_hash);
+        blockchain.state_tree = state_tree;
+        blockchain.base_height = snapshot.height;
+        Ok(blockchain)
+    }
+
+    pub fn base_height(&self) -> u64 {
+        self.base_height
+    }
+
+    // Lowest height the active chain can be rolled back to. There is no undo
+    // data below it, after bootstrapping from a snapshot or pruning.
+    pub fn min_fork_height(&self) -> u64 {
+        self.base_height.max(self.pruned_height)
+    }
+
+    pub fn prune_depth(&self) -> Option<u64> {
+        self.prune_depth
+    }
+
+    pub fn pruned_height(&self) -> u64 {
+        self.pruned_height
+    }
+
+    // Keep only the bodies and undo data of the last `depth` blocks, or
+    // everything if None. Blocks already pruned can't be brought back.
+    pub fn set_prune_depth(&mut self, depth: Option<u64>) -> Result<(), StorageError> {
+        let depth = depth.map(|depth| depth.max(1));
+        self.store.put_meta(PRUNE_DEPTH, encoding::encode(&depth))?;
+        self.prune_depth = depth;
+        self.prune()
+    }
+
+    // Delete bodies and undo data that have fallen out of the pruning
+    // window, on the active chain and on any side branch. Genesis is always
+    // kept.
+    fn prune(&mut self) -> Result<(), StorageError> {
+        let depth = match self.prune_depth {
+            Some(depth) => depth,
+            None => return Ok(()),
+        };
+
+        let target = self.height().saturating_sub(depth);
+        if target <= self.pruned_height {
+            return Ok(());
+        }
+
+        let mut batch = WriteBatch::default();
+        for hash in &self.chain[self.pruned_height as usize + 1..=target as usize] {
+            batch.push(WriteOp::DeleteBlock(*hash));
+            batch.push(WriteOp::DeleteUndo(*hash));
+        }
+
+        for tip in self.tree.tips() {
+            let mut node = self.tree.get(tip).unwrap();
+            while !self.is_active(&node.hash, node.height) && node.height > self.pruned_height {
+                if node.height <= target {
+                    batch.push(WriteOp::DeleteBlock(node.hash));
+                }
+                node = self.tree.get(&node.header.prev_hash).unwrap();
+            }
         }
+
+        batch.push(WriteOp::PutMeta(
+            PRUNED_HEIGHT.to_string(),
+            encoding::encode(&target),
+        ));
+        self.store.write(batch)?;
+
+        self.pruned_height = target;
+        Ok(())
+    }
+
+    // Whether a known block's body has been pruned
+    pub fn is_pruned(&self, hash: &[u8; 32]) -> bool {
+        self.tree
+            .get(hash)
+            .is_some_and(|node| node.height > 0 && node.height <= self.pruned_height)
+    }
+
+    // Block to send to a peer that asked for it. Pruned blocks are refused,
+    // even if their body is still on disk in a partly pruned segment.
+    pub fn serve_block(&self, hash: &[u8; 32]) -> Result<Option<Block>, StorageError> {
+        if self.is_pruned(hash) {
+            return Ok(None);
+        }
+        self.get_block(hash)
+    }
+
+    // Account state right after the block at `height` on the active chain
+    // was connected, rebuilt by undoing the blocks above it
+    pub fn state_at(&self, height: u64) -> Result<HashMap<[u8; 33], AccountState>, SnapshotError> {
+        if height < self.min_fork_height() || height > self.height() {
+            return Err(SnapshotError::UnavailableHeight(height));
+        }
+
+        let mut accounts: HashMap<_, _> = self.store.accounts()?.into_iter().collect();
+        for hash in self.chain[height as usize + 1..].iter().rev() {
+            let undo = self
+                .store
+                .get_undo(hash)?
+                .ok_or(SnapshotError::UnavailableHeight(height))?;
+            undo.revert(&mut accounts);
+        }
+
+        Ok(accounts)
+    }
+
+    // Account state right after the block `hash` on any branch, found by
+    // undoing the active chain back to where the branch forks and replaying
+    // the branch from there
+    pub fn state_after(
+        &self,
+        hash: &[u8; 32],
+    ) -> Result<HashMap<[u8; 33], AccountState>, BlockError> {
+        let mut branch = Vec::new();
+        let mut node = self.tree.get(hash).ok_or(BlockError::UnknownParent)?;
+        while !self.is_active(&node.hash, node.height) {
+            branch.push((node.hash, node.height));
+            node = self
+                .tree
+                .get(&node.header.prev_hash)
+                .ok_or(BlockError::UnknownParent)?;
+        }
+
+        let fork = node.height;
+        let mut accounts = self.state_at(fork).map_err(|_| BlockError::ReorgTooDeep {
+            fork,
+            min: self.min_fork_height(),
+        })?;
+
+        for (hash, height) in branch.iter().rev() {
+            let block = self
+                .get_block(hash)?
+                .ok_or(StorageError::MissingBody { height: *height })?;
+            let state = |account: &[u8; 33]| accounts.get(account).copied().unwrap_or_default();
+            let update = Self::compute_update(state, &block.data)?;
+            accounts.extend(update.accounts);
+        }
+
+        Ok(accounts)
+    }
+
+    // State root a child of `parent` with transactions `data` must commit to
+    pub fn state_root_after(
+        &self,
+        parent: &[u8; 32],
+        data: &[Transaction],
+    ) -> Result<[u8; 32], BlockError> {
+        let accounts = self.state_after(parent)?;
+        let state = |account: &[u8; 33]| accounts.get(account).copied().unwrap_or_default();
+        let update = Self::compute_update(state, data)?;
+
+        Ok(StateTree::new(accounts).root_after(&update.accounts))
+    }
+
+    // Prove an account's balance and nonce at the tip, for a light client
+    // to check against the tip's header
+    pub fn account_proof(&self, account: &[u8; 33]) -> AccountProof {
+        self.state_tree.prove(account)
+    }
+
+    // Export every account's state at `height` on the active chain
+    pub fn snapshot(&self, height: u64) -> Result<Snapshot, SnapshotError> {
+        let accounts = self.state_at(height)?;
+        Ok(Snapshot::new(height, self.chain[height as usize], accounts))
     }
 
-    // Return a reference to the block at the tip of the chain
-    pub fn tip(&self) -> &Block {
+    // Return the block at the tip of the chain. A chain bootstrapped from a
+    // snapshot has no body for its tip until a block is connected on top of
+    // it, so this is None there; use `tip_header` when the header will do.
+    pub fn tip(&self) -> Result<Option<Block>, StorageError> {
+        self.get_block(self.tip_hash())
+    }
+
+    pub fn tip_header(&self) -> &BlockHeader {
+        &self
+            .tree
+            .get(self.tip_hash())
+            .expect("active chain is in the tree")
+            .header
+    }
+
+    pub fn tip_hash(&self) -> &[u8; 32] {
         self.chain.last().expect("chain not empty")
     }
 
-    // Add a transaction to the mempool
-    pub fn add_transaction(&mut self, tx: Transaction) -> () {
-        self.mempool.push(tx);
+    pub fn height(&self) -> u64 {
+        self.chain.len() as u64 - 1
+    }
+
+    // Block at `height` on the active chain
+    pub fn block_at(&self, height: u64) -> Result<Option<Block>, StorageError> {
+        match self.chain.get(height as usize) {
+            Some(hash) => self.get_block(hash),
+            None => Ok(None),
+        }
+    }
+
+    pub fn header_at(&self, height: u64) -> Option<&BlockHeader> {
+        let hash = self.chain.get(height as usize)?;
+        self.tree.get(hash).map(|node| &node.header)
     }
 
-    // Process a block once it has been mined and being added to chain
-    pub fn add_block(&mut self, block: Block) -> () {
-        for tx in &block.data {
-            {
-                let recipient_balance = self.balances.entry(tx.recipient).or_insert(0);
-                *recipient_balance += tx.amount as i64;
+    // Any stored block, on the active chain or a side branch
+    pub fn get_block(&self, hash: &[u8; 32]) -> Result<Option<Block>, StorageError> {
+        self.store.get_block(hash)
+    }
+
+    pub fn is_active(&self, hash: &[u8; 32], height: u64) -> bool {
+        self.chain.get(height as usize) == Some(hash)
+    }
+
+    // Header at `height` on the branch ending at `hash`
+    pub fn ancestor(&self, hash: &[u8; 32], height: u64) -> Option<&BlockHeader> {
+        let mut node = self.tree.get(hash)?;
+
+        while node.height > height {
+            // Once the branch joins the active chain, index it directly
+            if self.is_active(&node.hash, node.height) {
+                return self.header_at(height);
             }
-            let sender_balance = self.balances.entry(tx.sender).or_insert(0);
-            *sender_balance -= tx.amount as i64;
+            node = self.tree.get(&node.header.prev_hash)?;
         }
+
+        (node.height == height).then_some(&node.header)
     }
 
-    // Mine a block
-    pub fn mine_block(&mut self) -> () {
-        let prev_block = self.tip();
+    // Confirmed state of an account
+    pub fn account(&self, account: &[u8; 33]) -> Result<AccountState, StorageError> {
+        Ok(self.store.get_account(account)?.unwrap_or_default())
+    }
 
-        let mut new_block = Block::new(prev_block.index + 1, prev_block.hash(), [0; 1024]);
+    // Confirmed balance of an account
+    pub fn balance(&self, account: &[u8; 33]) -> Result<u64, StorageError> {
+        Ok(self.account(account)?.balance)
+    }
 
-        for tx in &self.mempool {
-            new_block.data.push(tx.clone());
+    // Nonce the next transaction from an account must carry
+    pub fn nonce(&self, account: &[u8; 33]) -> Result<u64, StorageError> {
+        Ok(self.account(account)?.nonce)
+    }
+
+    pub fn indexes(&self) -> IndexOptions {
+        self.indexes
+    }
+
+    // Start or stop maintaining lookup indexes. Newly enabled indexes are
+    // built for the whole active chain and disabled ones are deleted, in the
+    // same write that records the new setting. The block index only needs
+    // headers, but the transaction and address indexes need every body, so
+    // changing them fails once any has been pruned or skipped by a snapshot.
+    pub fn set_indexes(&mut self, indexes: IndexOptions) -> Result<(), StorageError> {
+        let added = indexes.without(&self.indexes);
+        let removed = self.indexes.without(&indexes);
+
+        let mut batch = WriteBatch::default();
+        for (height, hash) in self.chain.iter().enumerate() {
+            if added.blocks {
+                batch.push(WriteOp::PutBlockHeight(*hash, height as u64));
+            }
+            if removed.blocks {
+                batch.push(WriteOp::DeleteBlockHeight(*hash));
+            }
+        }
+
+        let added = IndexOptions {
+            blocks: false,
+            ..added
+        };
+        let removed = IndexOptions {
+            blocks: false,
+            ..removed
+        };
+        if added.any() || removed.any() {
+            for height in 0..self.chain.len() as u64 {
+                let block = self
+                    .block_at(height)?
+                    .ok_or(StorageError::MissingBody { height })?;
+                batch.ops.extend(index::connect_ops(&added, &block));
+                batch.ops.extend(index::disconnect_ops(&removed, &block));
+            }
         }
+        batch.push(WriteOp::PutMeta(
+            INDEXES.to_string(),
+            encoding::encode(&indexes),
+        ));
+        self.store.write(batch)?;
 
-        self.mempool.clear();
+        self.indexes = indexes;
+        Ok(())
+    }
 
-        loop {
-            let new_block_hash = hex::encode(new_block.hash());
-            let target_prefix = "0".repeat(self.difficulty);
+    // Height of a block on the active chain. Needs the block index.
+    pub fn block_height(&self, hash: &[u8; 32]) -> Result<Option<u64>, StorageError> {
+        if !self.indexes.blocks {
+            return Ok(None);
+        }
+        self.store.get_block_height(hash)
+    }
+
+    // A confirmed transaction and where it is in the active chain. Needs
+    // the transaction index.
+    pub fn get_transaction(
+        &self,
+        txid: &[u8; 32],
+    ) -> Result<Option<(Transaction, TxLocation)>, StorageError> {
+        if !self.indexes.transactions {
+            return Ok(None);
+        }
+
+        let location = match self.store.get_tx_location(txid)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let tx = self
+            .get_block(&location.block)?
+            .and_then(|block| block.data.into_iter().nth(location.position as usize));
+        Ok(tx.map(|tx| (tx, location)))
+    }
+
+    // Txids and locations of the confirmed transactions sending to or from
+    // an account, oldest first. Needs the address index.
+    pub fn address_history(
+        &self,
+        account: &[u8; 33],
+    ) -> Result<Vec<([u8; 32], TxLocation)>, StorageError> {
+        if !self.indexes.addresses {
+            return Ok(Vec::new());
+        }
+        self.store.address_txs(account)
+    }
+
+    // Total amount and fees an account is already spending in the mempool
+    pub fn pending_spend(&self, account: &[u8; 33]) -> Result<u64, TransactionError> {
+        self.mempool
+            .from_sender(account)
+            .try_fold(0u64, |total, tx| {
+                total
+                    .checked_add(tx.total_cost()?)
+                    .ok_or(TransactionError::BalanceOverflow)
+            })
+    }
+
+    // Validate a transaction and add it to the mempool
+    pub fn add_transaction(&mut self, tx: Transaction) -> Result<(), TransactionError> {
+        if tx.is_coinbase() {
+            return Err(TransactionError::UnexpectedCoinbase);
+        }
+
+        tx.validate()?;
+
+        if self.mempool.contains(&tx.txid()) {
+            return Err(TransactionError::AlreadyInMempool);
+        }
 
-            if new_block_hash.starts_with(target_prefix.as_str()) {
+        let expected = self.nonce(&tx.sender)?;
+        if tx.nonce < expected {
+            return Err(TransactionError::NonceTooLow {
+                expected,
+                found: tx.nonce,
+            });
+        }
+        if tx.nonce - expected > MAX_NONCE_GAP {
+            return Err(TransactionError::NonceTooHigh {
+                expected,
+                found: tx.nonce,
+            });
+        }
+        if self.mempool.get(&tx.sender, tx.nonce).is_some() {
+            return Err(TransactionError::NonceAlreadyPending);
+        }
+
+        let available = self.balance(&tx.sender)?;
+        let required = self
+            .pending_spend(&tx.sender)?
+            .checked_add(tx.total_cost()?)
+            .ok_or(TransactionError::BalanceOverflow)?;
+        if required > available {
+            return Err(TransactionError::InsufficientFunds {
+                available,
+                required,
+            });
+        }
+
+        self.mempool.insert(tx)?;
+        Ok(())
+    }
+
+    // Compact target a child of `parent` must meet, derived from the
+    // timestamps and targets of the blocks on that branch
+    pub fn expected_bits(&self, parent: &[u8; 32]) -> Result<u32, BlockError> {
+        let height = self
+            .tree
+            .get(parent)
+            .ok_or(BlockError::UnknownParent)?
+            .height
+            + 1;
+        Ok(self.params.retarget.next_bits(height, |h| {
+            // The tree holds every header back to genesis, so a known
+            // block's ancestors are always there
+            self.ancestor(parent, h).expect("ancestors are in the tree")
+        }))
+    }
+
+    // Compact encoding of the target the next block must meet
+    pub fn bits(&self) -> u32 {
+        self.expected_bits(self.tip_hash())
+            .expect("active chain is in the tree")
+    }
+
+    pub fn target(&self) -> U256 {
+        U256::from_compact(self.bits()).expect("retargeting produces valid targets")
+    }
+
+    // Check that a block can extend its parent, wherever that is in the tree.
+    // Transactions are only checked against account state when connected.
+    // Anything built on a block that failed to connect is rejected, since
+    // the parent's flag covers all of its ancestors.
+    pub fn validate_block(&self, block: &Block) -> Result<(), BlockError> {
+        let parent = self
+            .tree
+            .get(&block.prev_hash)
+            .ok_or(BlockError::UnknownParent)?;
+
+        if parent.invalid {
+            return Err(BlockError::InvalidParent);
+        }
+
+        self.check_block(block, &block.prev_hash)
+    }
+
+    // Median timestamp of the last `MEDIAN_TIME_SPAN` blocks up to and
+    // including `hash`, or None if `hash` isn't in the tree
+    pub fn median_time_past(&self, hash: &[u8; 32]) -> Option<u64> {
+        let mut timestamps = Vec::with_capacity(MEDIAN_TIME_SPAN);
+        let mut node = Some(self.tree.get(hash)?);
+
+        while let Some(current) = node {
+            timestamps.push(current.header.timestamp);
+            if timestamps.len() == MEDIAN_TIME_SPAN || current.height == 0 {
                 break;
+            }
+            node = self.tree.get(&current.header.prev_hash);
+        }
+
+        timestamps.sort_unstable();
+        Some(timestamps[timestamps.len() / 2])
+    }
+
+    // Checks on a header against the branch it extends: height, timestamp,
+    // target and proof of work
+    fn check_header(&self, header: &BlockHeader, parent: &[u8; 32]) -> Result<(), BlockError> {
+        let height = self
+            .tree
+            .get(parent)
+            .ok_or(BlockError::UnknownParent)?
+            .height
+            + 1;
+        if header.index != height {
+            return Err(BlockError::InvalidIndex {
+                expected: height,
+                found: header.index,
+            });
+        }
+
+        let median = self
+            .median_time_past(parent)
+            .ok_or(BlockError::UnknownParent)?;
+        if header.timestamp <= median {
+            return Err(BlockError::TimestampTooOld {
+                median,
+                found: header.timestamp,
+            });
+        }
+
+        let max = self.clock.now().saturating_add(self.max_future_drift);
+        if header.timestamp > max {
+            return Err(BlockError::TimestampTooFarAhead {
+                max,
+                found: header.timestamp,
+            });
+        }
+
+        let expected_bits = self.expected_bits(parent)?;
+        if header.bits != expected_bits {
+            return Err(BlockError::InvalidBits {
+                expected: expected_bits,
+                found: header.bits,
+            });
+        }
+
+        if !header.meets_target() {
+            return Err(BlockError::InsufficientWork);
+        }
+
+        Ok(())
+    }
+
+    // Checks against the branch a block extends, before its transactions
+    // are applied
+    fn check_block(&self, block: &Block, parent: &[u8; 32]) -> Result<(), BlockError> {
+        self.check_header(&block.header(), parent)?;
+
+        if !block.verify_merkle_root() {
+            return Err(BlockError::InvalidMerkleRoot);
+        }
+
+        let size = block.encode().len();
+        if size > self.max_block_size {
+            return Err(BlockError::BlockTooLarge {
+                max: self.max_block_size,
+                found: size,
+            });
+        }
+
+        self.validate_coinbase(block)?;
+
+        for (i, tx) in block.data.iter().enumerate() {
+            tx.validate()
+                .map_err(|e| BlockError::InvalidTransaction(i, e))?;
+        }
+
+        Ok(())
+    }
+
+    // Re-check the whole active chain from genesis: linkage, proof of work,
+    // merkle roots, signatures and every other block rule, then replay the
+    // transactions and compare the result with the current account state.
+    // Reports the first block that fails. For a chain bootstrapped from a
+    // snapshot or pruned, only headers are checked up to the lowest height
+    // with state, and the replay starts from the state there.
+    pub fn validate_chain(&self) -> Result<(), ChainError> {
+        let base = self.min_fork_height();
+        let mut accounts = HashMap::new();
+
+        if base > 0 {
+            accounts = match self.state_at(base) {
+                Ok(accounts) => accounts,
+                Err(SnapshotError::Storage(e)) => return Err(e.into()),
+                Err(_) => return Err(ChainError::StateMismatch),
+            };
+        }
+
+        if base > 0 && base == self.base_height {
+            let snapshot = Snapshot::new(base, self.chain[base as usize], accounts.clone());
+            let committed = self
+                .store
+                .get_meta(SNAPSHOT)?
+                .and_then(|bytes| encoding::decode::<(u64, [u8; 32])>(&bytes).ok());
+            if committed != Some((base, snapshot.hash())) {
+                return Err(ChainError::StateMismatch);
+            }
+        }
+
+        let mut state_tree = StateTree::new(accounts.clone());
+        if base > 0 && self.header_at(base).unwrap().state_root != state_tree.root() {
+            return Err(ChainError::StateMismatch);
+        }
+
+        for height in 0..self.chain.len() as u64 {
+            let invalid = |error| ChainError::InvalidBlock { height, error };
+
+            let block = if height == 0 {
+                let block = self
+                    .block_at(0)?
+                    .ok_or(StorageError::MissingBody { height: 0 })?;
+                if block != self.params.genesis() {
+                    return Err(invalid(BlockError::InvalidGenesis));
+                }
+                if base > 0 {
+                    continue;
+                }
+                block
             } else {
-                new_block.nonce += 1;
+                let header = self.header_at(height).unwrap();
+                let parent = self.header_at(height - 1).unwrap();
+                if header.prev_hash != parent.hash() {
+                    return Err(invalid(BlockError::InvalidPrevHash));
+                }
+
+                // Only headers are kept up to the snapshot or pruned height
+                if height <= base {
+                    self.check_header(header, &header.prev_hash)
+                        .map_err(invalid)?;
+                    continue;
+                }
+
+                let block = self
+                    .block_at(height)?
+                    .ok_or(StorageError::MissingBody { height })?;
+                self.check_block(&block, &block.prev_hash)
+                    .map_err(invalid)?;
+                block
+            };
+
+            let state = |account: &[u8; 33]| accounts.get(account).copied().unwrap_or_default();
+            let update = Self::compute_update(state, &block.data).map_err(invalid)?;
+            for (account, state) in &update.accounts {
+                state_tree.insert(account, state);
+            }
+            accounts.extend(update.accounts);
+
+            if block.state_root != state_tree.root() {
+                return Err(invalid(BlockError::InvalidStateRoot));
+            }
+        }
+
+        let stored: HashMap<_, _> = self.store.accounts()?.into_iter().collect();
+        if accounts != stored {
+            return Err(ChainError::StateMismatch);
+        }
+
+        Ok(())
+    }
+
+    // Build a chain from a list of blocks, genesis first, stopping at the
+    // first block that doesn't extend the one before it
+    pub fn from_blocks(params: ChainParams, blocks: Vec<Block>) -> Result<Self, ChainError> {
+        let mut blocks = blocks.into_iter();
+        let genesis = blocks.next().ok_or(ChainError::Empty)?;
+
+        let mut blockchain = Self::new(params);
+        if genesis.hash() != *blockchain.tip_hash() {
+            return Err(ChainError::InvalidBlock {
+                height: 0,
+                error: BlockError::InvalidGenesis,
+            });
+        }
+
+        for (height, block) in (1..).zip(blocks) {
+            let invalid = |error| ChainError::InvalidBlock { height, error };
+
+            if block.prev_hash != *blockchain.tip_hash() {
+                return Err(invalid(BlockError::InvalidPrevHash));
+            }
+            blockchain.add_block(block).map_err(invalid)?;
+        }
+
+        Ok(blockchain)
+    }
+
+    // Encode the active chain, genesis first. Fails if any body was pruned
+    // or skipped by a snapshot, since the export couldn't be replayed.
+    pub fn encode_blocks(&self) -> Result<Vec<u8>, StorageError> {
+        let mut blocks = Vec::new();
+        for height in 0..self.chain.len() as u64 {
+            let block = self
+                .block_at(height)?
+                .ok_or(StorageError::MissingBody { height })?;
+            blocks.push(block);
+        }
+        Ok(encoding::encode(&blocks))
+    }
+
+    pub fn decode_blocks(bytes: &[u8]) -> Result<Vec<Block>, EncodingError> {
+        encoding::decode(bytes)
+    }
+
+    // Sum of the fees paid by the non-coinbase transactions
+    pub fn total_fees(data: &[Transaction]) -> Option<u64> {
+        data.iter()
+            .filter(|tx| !tx.is_coinbase())
+            .try_fold(0u64, |total, tx| total.checked_add(tx.fee))
+    }
+
+    // A block must start with exactly one coinbase, claiming no more than the
+    // subsidy for its height plus the fees of its transactions
+    fn validate_coinbase(&self, block: &Block) -> Result<(), BlockError> {
+        let coinbase = match block.data.first() {
+            Some(tx) if tx.is_coinbase() => tx,
+            _ => return Err(BlockError::MissingCoinbase),
+        };
+
+        if let Some(i) = block.data.iter().skip(1).position(|tx| tx.is_coinbase()) {
+            return Err(BlockError::InvalidTransaction(
+                i + 1,
+                TransactionError::UnexpectedCoinbase,
+            ));
+        }
+
+        if coinbase.nonce != block.index {
+            return Err(BlockError::InvalidCoinbaseHeight {
+                expected: block.index,
+                found: coinbase.nonce,
+            });
+        }
+
+        let allowed = Self::total_fees(&block.data)
+            .and_then(|fees| fees.checked_add(self.params.subsidy.subsidy_at(block.index)))
+            .unwrap_or(u64::MAX);
+        if coinbase.amount > allowed {
+            return Err(BlockError::CoinbaseTooLarge {
+                allowed,
+                found: coinbase.amount,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Work out the new balance and nonce of every account touched by the
+    // transactions, in order, without modifying the ledger
+    pub fn apply_transactions(&self, data: &[Transaction]) -> Result<StateUpdate, BlockError> {
+        let accounts = self.touched_accounts(data)?;
+        Self::compute_update(|account| accounts[account], data)
+    }
+
+    // Confirmed state of every sender and recipient in `data`
+    fn touched_accounts(
+        &self,
+        data: &[Transaction],
+    ) -> Result<HashMap<[u8; 33], AccountState>, StorageError> {
+        let mut accounts = HashMap::new();
+        for tx in data {
+            for account in [tx.sender, tx.recipient] {
+                if let Entry::Vacant(entry) = accounts.entry(account) {
+                    entry.insert(self.account(&account)?);
+                }
+            }
+        }
+        Ok(accounts)
+    }
+
+    // Same as `apply_transactions`, on top of any account state
+    pub fn compute_update(
+        state: impl Fn(&[u8; 33]) -> AccountState,
+        data: &[Transaction],
+    ) -> Result<StateUpdate, BlockError> {
+        let mut update = StateUpdate::default();
+
+        for (i, tx) in data.iter().enumerate() {
+            if tx.is_coinbase() {
+                let recipient = update
+                    .accounts
+                    .entry(tx.recipient)
+                    .or_insert_with(|| state(&tx.recipient));
+                recipient.balance = recipient.balance.checked_add(tx.amount).ok_or(
+                    BlockError::InvalidTransaction(i, TransactionError::BalanceOverflow),
+                )?;
+                continue;
+            }
+
+            let sender = update
+                .accounts
+                .entry(tx.sender)
+                .or_insert_with(|| state(&tx.sender));
+            let expected = sender.nonce;
+            if tx.nonce != expected {
+                let error = if tx.nonce < expected {
+                    TransactionError::NonceTooLow {
+                        expected,
+                        found: tx.nonce,
+                    }
+                } else {
+                    TransactionError::NonceTooHigh {
+                        expected,
+                        found: tx.nonce,
+                    }
+                };
+                return Err(BlockError::InvalidTransaction(i, error));
+            }
+            sender.nonce = expected + 1;
+
+            let required = tx
+                .total_cost()
+                .map_err(|e| BlockError::InvalidTransaction(i, e))?;
+            let available = sender.balance;
+            sender.balance =
+                available
+                    .checked_sub(required)
+                    .ok_or(BlockError::InvalidTransaction(
+                        i,
+                        TransactionError::InsufficientFunds {
+                            available,
+                            required,
+                        },
+                    ))?;
+
+            let recipient = update
+                .accounts
+                .entry(tx.recipient)
+                .or_insert_with(|| state(&tx.recipient));
+            recipient.balance =
+                recipient
+                    .balance
+                    .checked_add(tx.amount)
+                    .ok_or(BlockError::InvalidTransaction(
+                        i,
+                        TransactionError::BalanceOverflow,
+                    ))?;
+        }
+
+        Ok(update)
+    }
+
+    // Validate a block and add it to the tree. A block on the tip is
+    // connected straight away; one on a side branch is stored, and the chain
+    // reorganizes onto it if its branch now has the most cumulative work.
+    // A block with an unknown parent is held in the orphan pool, and any
+    // orphans waiting on a newly added block are added after it.
+    pub fn add_block(&mut self, block: Block) -> Result<(), BlockError> {
+        let hash = block.hash();
+        if self.tree.contains(&hash) || self.orphans.contains(&hash) {
+            return Err(BlockError::AlreadyKnown);
+        }
+
+        if !self.tree.contains(&block.prev_hash) {
+            // Only hold on to blocks that did the work they claim, against a
+            // target no easier than the chain ever allows. Otherwise a flood
+            // of free blocks could push real orphans out of the pool.
+            let within_limit = U256::from_compact(block.bits)
+                .is_some_and(|target| target <= self.params.retarget.pow_limit);
+            if !within_limit || !block.meets_target() {
+                return Err(BlockError::InsufficientWork);
+            }
+            self.orphans.insert(block, self.clock.now());
+            return Err(BlockError::UnknownParent);
+        }
+
+        let result = self.accept_block(block);
+        if self.tree.contains(&hash) {
+            self.connect_orphans(hash);
+        }
+        result
+    }
+
+    // Parents of orphan blocks that should be requested from peers
+    pub fn missing_blocks(&self) -> Vec<[u8; 32]> {
+        self.orphans.missing_parents()
+    }
+
+    fn accept_block(&mut self, block: Block) -> Result<(), BlockError> {
+        let hash = block.hash();
+        self.validate_block(&block)?;
+
+        // A block at a pruned height is on a branch that can no longer be
+        // reorganized to, so its body would only be pruned again
+        let header = block.header();
+        if header.index > 0 && header.index <= self.pruned_height {
+            self.store.put_header(header.clone())?;
+        } else {
+            self.store.put_block(block)?;
+        }
+
+        if self.chain.last() == Some(&header.prev_hash) {
+            self.tree.insert(header);
+            self.connect_block(hash)?;
+            self.prune_mempool()?;
+            self.prune()?;
+            return Ok(());
+        }
+
+        let tip_work = self.tree.get(self.tip_hash()).unwrap().cumulative_work;
+        if self.tree.insert(header).cumulative_work > tip_work {
+            self.reorganize(hash)?;
+            self.prune()?;
+        }
+
+        Ok(())
+    }
+
+    // Add the orphans descending from `parent`, now that it's in the tree
+    fn connect_orphans(&mut self, parent: [u8; 32]) {
+        let mut parents = vec![parent];
+
+        while let Some(parent) = parents.pop() {
+            for block in self.orphans.take_children(&parent) {
+                let hash = block.hash();
+                let _ = self.accept_block(block);
+                if self.tree.contains(&hash) {
+                    parents.push(hash);
+                } else {
+                    // The child was rejected, so nothing built on it can connect
+                    self.orphans.remove_descendants(&hash);
+                }
+            }
+        }
+    }
+
+    // Apply a block that extends the tip, keeping undo data to revert it.
+    // The new account state, undo data and tip are written in one batch. A
+    // block that fails validation is marked invalid and the ledger is left
+    // unchanged. A storage error says nothing about the block, so it isn't
+    // marked and can be connected again once the store recovers.
+    fn connect_block(&mut self, hash: [u8; 32]) -> Result<(), BlockError> {
+        let block = self.get_block(&hash)?.ok_or(StorageError::MissingBody {
+            height: self.height() + 1,
+        })?;
+
+        let update = match self.apply_transactions(&block.data) {
+            Ok(update) => update,
+            Err(BlockError::Storage(e)) => return Err(BlockError::Storage(e)),
+            Err(e) => {
+                self.invalidate(&hash)?;
+                return Err(e);
             }
+        };
+
+        if block.state_root != self.state_tree.root_after(&update.accounts) {
+            self.invalidate(&hash)?;
+            return Err(BlockError::InvalidStateRoot);
+        }
+
+        let mut previous = HashMap::new();
+        for account in update.accounts.keys() {
+            previous.insert(*account, self.store.get_account(account)?);
+        }
+        let undo = BlockUndo::capture(&update.accounts, |account| previous[account]);
+
+        let mut batch = WriteBatch::default();
+        for (account, state) in &update.accounts {
+            batch.push(WriteOp::PutAccount(*account, *state));
         }
+        batch.push(WriteOp::PutUndo(hash, undo));
+        batch.ops.extend(index::connect_ops(&self.indexes, &block));
+        batch.push(WriteOp::PutMeta(BEST_TIP.to_string(), hash.to_vec()));
+        self.store.write(batch)?;
 
-        self.add_block(new_block);
+        for (account, state) in &update.accounts {
+            self.state_tree.insert(account, state);
+        }
+        self.chain.push(hash);
+        Ok(())
+    }
+
+    // Flag a block that failed to connect, and everything built on it, and
+    // record it so the branch stays rejected after a restart
+    fn invalidate(&mut self, hash: &[u8; 32]) -> Result<(), StorageError> {
+        self.tree.mark_invalid(hash);
+
+        let mut invalid: Vec<[u8; 32]> = match self.store.get_meta(INVALID)? {
+            Some(bytes) => encoding::decode(&bytes)?,
+            None => Vec::new(),
+        };
+        invalid.push(*hash);
+        self.store.put_meta(INVALID, encoding::encode(&invalid))
+    }
+
+    // Revert the tip block and return it
+    fn disconnect_tip(&mut self) -> Result<Block, BlockError> {
+        assert!(self.chain.len() > 1, "cannot disconnect genesis");
+
+        let (hash, height) = (*self.tip_hash(), self.height());
+        let block = self
+            .get_block(&hash)?
+            .ok_or(StorageError::MissingBody { height })?;
+        let undo = self
+            .store
+            .get_undo(&hash)?
+            .ok_or(StorageError::MissingUndo { height })?;
+
+        let mut batch = WriteBatch::default();
+        for entry in &undo.entries {
+            batch.push(match entry.previous {
+                Some(state) => WriteOp::PutAccount(entry.account, state),
+                None => WriteOp::DeleteAccount(entry.account),
+            });
+        }
+        batch.push(WriteOp::DeleteUndo(hash));
+        batch
+            .ops
+            .extend(index::disconnect_ops(&self.indexes, &block));
+        batch.push(WriteOp::PutMeta(
+            BEST_TIP.to_string(),
+            block.prev_hash.to_vec(),
+        ));
+        self.store.write(batch)?;
+
+        for entry in undo.entries {
+            match entry.previous {
+                Some(state) => self.state_tree.insert(&entry.account, &state),
+                None => self.state_tree.remove(&entry.account),
+            }
+        }
+        self.chain.pop();
+        Ok(block)
+    }
+
+    // Switch the active chain to the branch ending at `new_tip`: disconnect
+    // blocks back to the fork point, connect the new branch, then return
+    // transactions from the old branch to the mempool if they're still
+    // valid and evict pending ones that no longer are. If a block on the new
+    // branch fails, the old chain is restored.
+    fn reorganize(&mut self, new_tip: [u8; 32]) -> Result<(), BlockError> {
+        let mut branch = Vec::new();
+        let mut hash = new_tip;
+        loop {
+            let node = self.tree.get(&hash).ok_or(BlockError::UnknownParent)?;
+            if self.is_active(&hash, node.height) {
+                break;
+            }
+            branch.push(hash);
+            hash = node.header.prev_hash;
+        }
+        branch.reverse();
+        let fork = self
+            .tree
+            .get(&hash)
+            .ok_or(BlockError::UnknownParent)?
+            .height;
+        if fork < self.min_fork_height() {
+            return Err(BlockError::ReorgTooDeep {
+                fork,
+                min: self.min_fork_height(),
+            });
+        }
+
+        let mut disconnected = Vec::new();
+        while self.height() > fork {
+            disconnected.push(self.disconnect_tip()?);
+        }
+        disconnected.reverse();
+
+        for (i, hash) in branch.iter().enumerate() {
+            if let Err(e) = self.connect_block(*hash) {
+                // Each step commits its own batch, so if the store fails
+                // while restoring, the chain stops at a consistent tip
+                // somewhere between the fork and the old tip
+                for _ in 0..i {
+                    self.disconnect_tip()?;
+                }
+                for block in &disconnected {
+                    self.connect_block(block.hash())?;
+                }
+                return Err(e);
+            }
+        }
+
+        let returned = disconnected
+            .into_iter()
+            .flat_map(|block| block.data)
+            .filter(|tx| !tx.is_coinbase())
+            .collect();
+        self.revalidate_mempool(returned)?;
+
+        Ok(())
+    }
+
+    // Re-check every pending transaction, plus those returned from a
+    // disconnected branch, against the new tip's state. The pool is rebuilt
+    // in nonce order, so anything whose balance or nonce no longer holds up
+    // is dropped.
+    fn revalidate_mempool(&mut self, returned: Vec<Transaction>) -> Result<(), BlockError> {
+        let max_bytes = self.mempool.max_bytes;
+        let pending = std::mem::replace(&mut self.mempool, Mempool::new(max_bytes));
+
+        // Returned transactions were confirmed first, so they win a nonce
+        // clash with a pending one
+        let mut candidates: Vec<Transaction> = returned
+            .into_iter()
+            .chain(pending.iter().cloned())
+            .collect();
+        candidates.sort_by_key(|tx| (tx.sender, tx.nonce));
+
+        for tx in candidates {
+            if let Err(TransactionError::Storage(e)) = self.add_transaction(tx) {
+                return Err(BlockError::Storage(e));
+            }
+        }
+        Ok(())
+    }
+
+    fn prune_mempool(&mut self) -> Result<(), StorageError> {
+        let nonces = self.pending_nonces()?;
+        self.mempool.prune(|account| nonces[account]);
+        Ok(())
+    }
+
+    // Confirmed nonce of every sender with a transaction in the mempool
+    fn pending_nonces(&self) -> Result<HashMap<[u8; 33], u64>, StorageError> {
+        let mut nonces = HashMap::new();
+        for tx in self.mempool.iter() {
+            if let Entry::Vacant(entry) = nonces.entry(tx.sender) {
+                entry.insert(self.nonce(&tx.sender)?);
+            }
+        }
+        Ok(nonces)
+    }
+
+    // Build an unsolved block on the tip with the most profitable mempool
+    // transactions that fit, paying the subsidy and fees to the miner. The
+    // mempool is left untouched until the block is accepted.
+    pub fn block_template(&self, miner_address: [u8; 33]) -> Result<Block, StorageError> {
+        let (index, prev_hash) = (self.tip_header().index + 1, *self.tip_hash());
+
+        let reward = self.params.subsidy.subsidy_at(index);
+        let coinbase = Transaction::coinbase(miner_address, reward, index);
+        let overhead = Block::new(index, prev_hash, self.bits(), vec![coinbase.clone()])
+            .encode()
+            .len();
+
+        let nonces = self.pending_nonces()?;
+        let selected = self.mempool.select(
+            |account| nonces[account],
+            self.max_block_size.saturating_sub(overhead),
+        );
+
+        // Leave out transactions that no longer apply to the tip, and the
+        // rest of their sender's queue, rather than build a block that is
+        // bound to be rejected
+        let mut accounts = self.touched_accounts(&selected)?;
+        let mut skipped = HashSet::new();
+        let selected: Vec<Transaction> = selected
+            .into_iter()
+            .filter(|tx| {
+                if skipped.contains(&tx.sender) {
+                    return false;
+                }
+                match Self::compute_update(|account| accounts[account], std::slice::from_ref(tx)) {
+                    Ok(update) => {
+                        accounts.extend(update.accounts);
+                        true
+                    }
+                    Err(_) => {
+                        skipped.insert(tx.sender);
+                        false
+                    }
+                }
+            })
+            .collect();
+
+        let fees = Self::total_fees(&selected).expect("mempool fees fit in a u64");
+        let mut data = vec![Transaction::coinbase(
+            miner_address,
+            reward.saturating_add(fees),
+            index,
+        )];
+        data.extend(selected);
+
+        // Stamp with the local clock, but never at or before the median
+        // time past or the block would be rejected
+        let mut block = Block::new(index, prev_hash, self.bits(), data);
+        let earliest = self
+            .median_time_past(&prev_hash)
+            .map_or(0, |median| median + 1);
+        block.timestamp = self.clock.now().max(earliest);
+
+        // A body that no longer applies is rejected whatever its state
+        // root, so the root is only filled in for one that does
+        if let Ok(update) = self.apply_transactions(&block.data) {
+            block.state_root = self.state_tree.root_after(&update.accounts);
+        }
+        Ok(block)
+    }
+
+    // Accept a solved block from any miner. Its transactions leave the
+    // mempool once it is connected.
+    pub fn submit_block(&mut self, block: Block) -> Result<[u8; 32], BlockError> {
+        let hash = block.hash();
+        self.add_block(block)?;
+        Ok(hash)
+    }
+
+    // Mine a block template with the built-in miner. Returns the new block's
+    // hash, or None if mining was cancelled.
+    pub fn mine_block(&mut self, miner_address: [u8; 33]) -> Result<Option<[u8; 32]>, BlockError> {
+        let template = self.block_template(miner_address)?;
+
+        match self.miner.mine(template, self.clock.as_ref()) {
+            Some(block) => self.submit_block(block).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl From<StorageError> for BlockError {
+    fn from(e: StorageError) -> Self {
+        BlockError::Storage(e.to_string())
+    }
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::AlreadyKnown => write!(f, "block is already known"),
+            BlockError::UnknownParent => write!(f, "previous block is unknown"),
+            BlockError::InvalidParent => write!(f, "previous block is invalid"),
+            BlockError::InvalidGenesis => {
+                write!(f, "genesis block does not match the chain parameters")
+            }
+            BlockError::InvalidPrevHash => {
+                write!(f, "previous hash does not match the block before it")
+            }
+            BlockError::InvalidIndex { expected, found } => {
+                write!(f, "expected block index {}, found {}", expected, found)
+            }
+            BlockError::InvalidBits { expected, found } => {
+                write!(
+                    f,
+                    "block claims bits {:08x}, expected {:08x}",
+                    found, expected
+                )
+            }
+            BlockError::InsufficientWork => write!(f, "hash does not meet the target"),
+            BlockError::InvalidMerkleRoot => write!(f, "merkle root does not match the block body"),
+            BlockError::InvalidTransaction(i, e) => write!(f, "transaction {} invalid: {}", i, e),
+            BlockError::MissingCoinbase => write!(f, "block does not start with a coinbase"),
+            BlockError::InvalidCoinbaseHeight { expected, found } => {
+                write!(f, "coinbase height is {}, expected {}", found, expected)
+            }
+            BlockError::CoinbaseTooLarge { allowed, found } => {
+                write!(f, "coinbase claims {}, only {} allowed", found, allowed)
+            }
+            BlockError::BlockTooLarge { max, found } => {
+                write!(f, "block is {} bytes, limit is {}", found, max)
+            }
+            BlockError::Storage(e) => write!(f, "could not store block: {}", e),
+            BlockError::TimestampTooOld { median, found } => write!(
+                f,
+                "timestamp {} is not after the median time past {}",
+                found, median
+            ),
+            BlockError::TimestampTooFarAhead { max, found } => {
+                write!(f, "timestamp {} is later than {}", found, max)
+            }
+            BlockError::ReorgTooDeep { fork, min } => write!(
+                f,
+                "branch forks at height {}, below the lowest reversible height {}",
+                fork, min
+            ),
+            BlockError::InvalidStateRoot => {
+                write!(f, "state root does not match the resulting account state")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+impl From<StorageError> for TransactionError {
+    fn from(e: StorageError) -> Self {
+        TransactionError::Storage(e.to_string())
+    }
+}
+
+impl From<StorageError> for ChainError {
+    fn from(e: StorageError) -> Self {
+        ChainError::Storage(e.to_string())
     }
 }
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::Empty => write!(f, "chain has no genesis block"),
+            ChainError::InvalidBlock { height, error } => {
+                write!(f, "block {} is invalid: {}", height, error)
+            }
+            ChainError::StateMismatch => {
+                write!(f, "account state does not match the chain")
+            }
+            ChainError::Storage(e) => write!(f, "could not read chain: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
@@ -0,0 +1,222 @@
+use std::io;
+use std::path::Path;
+
+use crate::chain::block::{Block, BlockHeader};
+use crate::chain::encoding;
+use crate::chain::index::TxLocation;
+use crate::chain::state::AccountState;
+use crate::chain::storage::{BlockStore, StorageError};
+use crate::chain::store::{ChainStore, WriteBatch, WriteOp};
+use crate::chain::undo::BlockUndo;
+
+// Key prefixes in the database
+const HEADER: u8 = b'h';
+const ACCOUNT: u8 = b'a';
+const UNDO: u8 = b'u';
+const META: u8 = b'm';
+const BLOCK_HEIGHT: u8 = b'i';
+const TX_LOCATION: u8 = b't';
+const ADDRESS_TX: u8 = b'x';
+// Marks a block whose body is being deleted, until it's dropped from the
+// block index
+const PRUNED: u8 = b'p';
+
+// On-disk store for nodes. Block bodies go to the segmented flat files;
+// headers, account state, undo data and metadata live in an embedded
+// key-value database, where each batch is applied atomically.
+pub struct DiskStore {
+    pub blocks: BlockStore,
+    db: sled::Db,
+}
+
+fn key(prefix: u8, id: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(1 + id.len());
+    key.push(prefix);
+    key.extend_from_slice(id);
+    key
+}
+
+// Big-endian height and position sort an account's entries in chain order
+fn address_key(account: &[u8; 33], location: &TxLocation) -> Vec<u8> {
+    let mut key = key(ADDRESS_TX, account);
+    key.extend_from_slice(&location.height.to_be_bytes());
+    key.extend_from_slice(&location.position.to_be_bytes());
+    key
+}
+
+impl DiskStore {
+    // Open the store in `dir`, creating it if needed. Bodies whose pruning
+    // was interrupted are dropped from the block index now.
+    pub fn open(dir: impl AsRef<Path>, magic: [u8; 4]) -> Result<Self, StorageError> {
+        let dir = dir.as_ref();
+
+        let db = sled::open(dir.join("state"))?;
+        let mut store = Self {
+            blocks: BlockStore::open(dir.join("blocks"), magic, db.open_tree("blocks")?)?,
+            db,
+        };
+
+        let mut pruned = Vec::new();
+        for entry in store.db.scan_prefix([PRUNED]) {
+            let hash: [u8; 32] = entry?.0[1..]
+                .try_into()
+                .map_err(|_| StorageError::Database("malformed pruned key".to_string()))?;
+            pruned.push(hash);
+        }
+        store.finish_pruning(pruned)?;
+
+        Ok(store)
+    }
+
+    // Drop pruned bodies from the block index, then their markers. A crash
+    // in between leaves the markers to be finished on the next open.
+    fn finish_pruning(&mut self, pruned: Vec<[u8; 32]>) -> Result<(), StorageError> {
+        let mut batch = sled::Batch::default();
+        for hash in pruned {
+            self.blocks.remove(&hash)?;
+            batch.remove(key(PRUNED, &hash));
+        }
+        self.db.apply_batch(batch)?;
+        Ok(())
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(&self, key: &[u8]) -> Result<Option<T>, StorageError> {
+        match self.db.get(key)? {
+            Some(value) => Ok(Some(encoding::decode(&value)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl ChainStore for DiskStore {
+    fn get_block(&self, hash: &[u8; 32]) -> Result<Option<Block>, StorageError> {
+        self.blocks.get(hash)
+    }
+
+    fn get_header(&self, hash: &[u8; 32]) -> Result<Option<BlockHeader>, StorageError> {
+        self.get(&key(HEADER, hash))
+    }
+
+    fn headers(&self) -> Result<Vec<BlockHeader>, StorageError> {
+        self.db
+            .scan_prefix([HEADER])
+            .map(|entry| Ok(encoding::decode(&entry?.1)?))
+            .collect()
+    }
+
+    fn get_account(&self, account: &[u8; 33]) -> Result<Option<AccountState>, StorageError> {
+        self.get(&key(ACCOUNT, account))
+    }
+
+    fn accounts(&self) -> Result<Vec<([u8; 33], AccountState)>, StorageError> {
+        self.db
+            .scan_prefix([ACCOUNT])
+            .map(|entry| {
+                let (key, value) = entry?;
+                let account = key[1..]
+                    .try_into()
+                    .map_err(|_| StorageError::Database("malformed account key".to_string()))?;
+                Ok((account, encoding::decode(&value)?))
+            })
+            .collect()
+    }
+
+    fn get_undo(&self, hash: &[u8; 32]) -> Result<Option<BlockUndo>, StorageError> {
+        self.get(&key(UNDO, hash))
+    }
+
+    fn get_meta(&self, key_name: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self
+            .db
+            .get(key(META, key_name.as_bytes()))?
+            .map(|value| value.to_vec()))
+    }
+
+    fn get_block_height(&self, hash: &[u8; 32]) -> Result<Option<u64>, StorageError> {
+        self.get(&key(BLOCK_HEIGHT, hash))
+    }
+
+    fn get_tx_location(&self, txid: &[u8; 32]) -> Result<Option<TxLocation>, StorageError> {
+        self.get(&key(TX_LOCATION, txid))
+    }
+
+    fn address_txs(&self, account: &[u8; 33]) -> Result<Vec<([u8; 32], TxLocation)>, StorageError> {
+        self.db
+            .scan_prefix(key(ADDRESS_TX, account))
+            .map(|entry| Ok(encoding::decode(&entry?.1)?))
+            .collect()
+    }
+
+    // Bodies are appended and synced first. If the database write is then
+    // lost to a crash, all that's left is a body nothing refers to. Pruned
+    // bodies are only removed once the database has recorded it.
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StorageError> {
+        let mut db_batch = sled::Batch::default();
+        let mut pruned = Vec::new();
+
+        for op in batch.ops {
+            match op {
+                WriteOp::PutBlock(block) => {
+                    self.blocks.append(&block)?;
+                    let header = block.header();
+                    db_batch.remove(key(PRUNED, &header.hash()));
+                    db_batch.insert(key(HEADER, &header.hash()), header.encode());
+                }
+                WriteOp::DeleteBlock(hash) => {
+                    db_batch.insert(key(PRUNED, &hash), &[]);
+                    pruned.push(hash);
+                }
+                WriteOp::PutHeader(header) => {
+                    db_batch.insert(key(HEADER, &header.hash()), header.encode());
+                }
+                WriteOp::PutAccount(account, state) => {
+                    db_batch.insert(key(ACCOUNT, &account), encoding::encode(&state));
+                }
+                WriteOp::DeleteAccount(account) => db_batch.remove(key(ACCOUNT, &account)),
+                WriteOp::PutUndo(hash, undo) => {
+                    db_batch.insert(key(UNDO, &hash), encoding::encode(&undo));
+                }
+                WriteOp::DeleteUndo(hash) => db_batch.remove(key(UNDO, &hash)),
+                WriteOp::PutMeta(key_name, value) => {
+                    db_batch.insert(key(META, key_name.as_bytes()), value);
+                }
+                WriteOp::PutBlockHeight(hash, height) => {
+                    db_batch.insert(key(BLOCK_HEIGHT, &hash), encoding::encode(&height));
+                }
+                WriteOp::DeleteBlockHeight(hash) => db_batch.remove(key(BLOCK_HEIGHT, &hash)),
+                WriteOp::PutTxLocation(txid, location) => {
+                    db_batch.insert(key(TX_LOCATION, &txid), encoding::encode(&location));
+                }
+                WriteOp::DeleteTxLocation(txid) => db_batch.remove(key(TX_LOCATION, &txid)),
+                WriteOp::PutAddressTx(account, txid, location) => {
+                    db_batch.insert(
+                        address_key(&account, &location),
+                        encoding::encode(&(txid, location)),
+                    );
+                }
+                WriteOp::DeleteAddressTx(account, location) => {
+                    db_batch.remove(address_key(&account, &location));
+                }
+            }
+        }
+
+        self.db.apply_batch(db_batch)?;
+        self.db.flush()?;
+        self.finish_pruning(pruned)
+    }
+}
+
+// sled reports a held lock as an `Other` I/O error, telling it apart only
+// by the message
+fn is_lock_error(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock || e.to_string().contains("could not acquire lock")
+}
+
+impl From<sled::Error> for StorageError {
+    fn from(e: sled::Error) -> Self {
+        match e {
+            sled::Error::Io(e) if is_lock_error(&e) => StorageError::Locked,
+            e => StorageError::Database(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use bincode::Options;
+use serde::de::{self, DeserializeOwned, Deserializer, SeqAccess, Visitor};
+use serde::ser::{SerializeTuple, Serializer};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Bumped whenever the layout of an encoded type changes
+pub const ENCODING_VERSION: u8 = 1;
+
+#[derive(Debug, PartialEq)]
+pub enum EncodingError {
+    Empty,
+    UnsupportedVersion(u8),
+    Malformed(String),
+}
+
+// Fixed-width little-endian integers, and no trailing bytes allowed, so every
+// value has exactly one valid encoding
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+        .reject_trailing_bytes()
+}
+
+// Encode a value as a version byte followed by its bincode representation
+pub fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![ENCODING_VERSION];
+    options()
+        .serialize_into(&mut bytes, value)
+        .expect("in-memory serialization cannot fail");
+    bytes
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, EncodingError> {
+    let (version, body) = bytes.split_first().ok_or(EncodingError::Empty)?;
+
+    if *version != ENCODING_VERSION {
+        return Err(EncodingError::UnsupportedVersion(*version));
+    }
+
+    options()
+        .deserialize(body)
+        .map_err(|e| EncodingError::Malformed(e.to_string()))
+}
+
+// serde only implements its traits for arrays up to 32 elements, so keys and
+// signatures go through these helpers. Arrays are written as fixed-size
+// tuples, which bincode encodes without a length prefix.
+pub mod byte_array {
+    use super::*;
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        bytes: &[u8; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(N)?;
+        for byte in bytes {
+            tuple.serialize_element(byte)?;
+        }
+        tuple.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[u8; N], D::Error> {
+        deserializer.deserialize_tuple(N, ByteArrayVisitor::<N>)
+    }
+
+    struct ByteArrayVisitor<const N: usize>;
+
+    impl<'de, const N: usize> Visitor<'de> for ByteArrayVisitor<N> {
+        type Value = [u8; N];
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "an array of {} bytes", N)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut bytes = [0; N];
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+            }
+            Ok(bytes)
+        }
+    }
+}
+
+pub mod option_byte_array {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Borrowed<'a, const N: usize>(#[serde(with = "byte_array")] &'a [u8; N]);
+
+    #[derive(Deserialize)]
+    struct Owned<const N: usize>(#[serde(with = "byte_array")] [u8; N]);
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        bytes: &Option<[u8; N]>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        bytes.as_ref().map(Borrowed).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<Option<[u8; N]>, D::Error> {
+        let bytes: Option<Owned<N>> = Option::deserialize(deserializer)?;
+        Ok(bytes.map(|Owned(bytes)| bytes))
+    }
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingError::Empty => write!(f, "no bytes to decode"),
+            EncodingError::UnsupportedVersion(v) => write!(f, "unsupported encoding version {}", v),
+            EncodingError::Malformed(e) => write!(f, "malformed encoding: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
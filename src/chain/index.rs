@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+use crate::chain::block::Block;
+use crate::chain::store::WriteOp;
+
+// Metadata key holding the indexes that are currently maintained
+pub const INDEXES: &str = "indexes";
+
+// Which lookup indexes the chain keeps for the active chain. They cost disk
+// space and write time, so all of them are off by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct IndexOptions {
+    // Block hash to height
+    pub blocks: bool,
+    // Txid to the block and position it was confirmed at
+    pub transactions: bool,
+    // Public key to the transactions that send to or from it
+    pub addresses: bool,
+}
+
+impl IndexOptions {
+    pub fn all() -> Self {
+        Self {
+            blocks: true,
+            transactions: true,
+            addresses: true,
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.blocks || self.transactions || self.addresses
+    }
+
+    // Indexes enabled here but not in `other`
+    pub fn without(&self, other: &Self) -> Self {
+        Self {
+            blocks: self.blocks && !other.blocks,
+            transactions: self.transactions && !other.transactions,
+            addresses: self.addresses && !other.addresses,
+        }
+    }
+}
+
+// Where a transaction sits in the active chain
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TxLocation {
+    pub block: [u8; 32],
+    pub height: u64,
+    pub position: u32,
+}
+
+// Index entries to add when `block` is connected
+pub fn connect_ops(options: &IndexOptions, block: &Block) -> Vec<WriteOp> {
+    let hash = block.hash();
+    let mut ops = Vec::new();
+
+    if options.blocks {
+        ops.push(WriteOp::PutBlockHeight(hash, block.index));
+    }
+
+    for (txid, location, accounts) in entries(block, hash) {
+        if options.transactions {
+            ops.push(WriteOp::PutTxLocation(txid, location));
+        }
+        if options.addresses {
+            for account in accounts {
+                ops.push(WriteOp::PutAddressTx(account, txid, location));
+            }
+        }
+    }
+
+    ops
+}
+
+// Index entries to remove when `block` is disconnected
+pub fn disconnect_ops(options: &IndexOptions, block: &Block) -> Vec<WriteOp> {
+    let hash = block.hash();
+    let mut ops = Vec::new();
+
+    if options.blocks {
+        ops.push(WriteOp::DeleteBlockHeight(hash));
+    }
+
+    for (txid, location, accounts) in entries(block, hash) {
+        if options.transactions {
+            ops.push(WriteOp::DeleteTxLocation(txid));
+        }
+        if options.addresses {
+            for account in accounts {
+                ops.push(WriteOp::DeleteAddressTx(account, location));
+            }
+        }
+    }
+
+    ops
+}
+
+// Every transaction in a block with its location and the accounts it touches
+fn entries(
+    block: &Block,
+    hash: [u8; 32],
+) -> impl Iterator<Item = ([u8; 32], TxLocation, Vec<[u8; 33]>)> + '_ {
+    block.data.iter().enumerate().map(move |(position, tx)| {
+        let location = TxLocation {
+            block: hash,
+            height: block.index,
+            position: position as u32,
+        };
+
+        let mut accounts = vec![tx.recipient];
+        if !tx.is_coinbase() && tx.sender != tx.recipient {
+            accounts.push(tx.sender);
+        }
+
+        (tx.txid(), location, accounts)
+    })
+}
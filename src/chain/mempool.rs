@@ -0,0 +1,237 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+
+use crate::chain::transaction::{Transaction, TransactionError};
+
+// Default limit on the total encoded size of pending transactions
+pub const DEFAULT_MAX_BYTES: usize = 10_000_000;
+
+struct Entry {
+    tx: Transaction,
+    size: usize,
+}
+
+// Pending transactions grouped by sender and ordered by nonce. Transactions
+// whose nonce is ahead of the sender's next nonce stay queued until the gap
+// is filled. When the pool is full the lowest fee rate transactions are
+// evicted, always from the end of a sender's queue so no gaps are created.
+pub struct Mempool {
+    pub max_bytes: usize,
+    total_bytes: usize,
+    by_sender: HashMap<[u8; 33], BTreeMap<u64, Entry>>,
+    txids: HashSet<[u8; 32]>,
+}
+
+// Order two transactions by fee per encoded byte
+fn cmp_fee_rate(fee_a: u64, size_a: usize, fee_b: u64, size_b: usize) -> Ordering {
+    (fee_a as u128 * size_b as u128).cmp(&(fee_b as u128 * size_a as u128))
+}
+
+// Next transaction a sender can have mined, ranked by fee rate
+struct Candidate<'a> {
+    entry: &'a Entry,
+}
+
+impl Ord for Candidate<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_fee_rate(
+            self.entry.tx.fee,
+            self.entry.size,
+            other.entry.tx.fee,
+            other.entry.size,
+        )
+        .then_with(|| other.entry.tx.sender.cmp(&self.entry.tx.sender))
+    }
+}
+
+impl PartialOrd for Candidate<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate<'_> {}
+
+impl Mempool {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            total_bytes: 0,
+            by_sender: HashMap::new(),
+            txids: HashSet::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.txids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txids.is_empty()
+    }
+
+    // Total encoded size of every pending transaction
+    pub fn size(&self) -> usize {
+        self.total_bytes
+    }
+
+    pub fn contains(&self, txid: &[u8; 32]) -> bool {
+        self.txids.contains(txid)
+    }
+
+    // Pending transaction from a sender with the given nonce, if any
+    pub fn get(&self, sender: &[u8; 33], nonce: u64) -> Option<&Transaction> {
+        Some(&self.by_sender.get(sender)?.get(&nonce)?.tx)
+    }
+
+    // Every pending transaction from a sender, in nonce order
+    pub fn from_sender(&self, sender: &[u8; 33]) -> impl Iterator<Item = &Transaction> {
+        self.by_sender
+            .get(sender)
+            .into_iter()
+            .flat_map(|queue| queue.values().map(|entry| &entry.tx))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Transaction> {
+        self.by_sender
+            .values()
+            .flat_map(|queue| queue.values().map(|entry| &entry.tx))
+    }
+
+    // Add a transaction, evicting cheaper ones if the pool is full. Returns
+    // the evicted transactions.
+    pub fn insert(&mut self, tx: Transaction) -> Result<Vec<Transaction>, TransactionError> {
+        let size = tx.encode().len();
+        let victims = self.plan_eviction(&tx, size)?;
+
+        let evicted = victims
+            .into_iter()
+            .filter_map(|(sender, nonce)| self.remove(&sender, nonce))
+            .collect();
+
+        self.txids.insert(tx.txid());
+        self.total_bytes += size;
+        self.by_sender
+            .entry(tx.sender)
+            .or_default()
+            .insert(tx.nonce, Entry { tx, size });
+
+        Ok(evicted)
+    }
+
+    // Pick the queue tails to drop so `tx` fits. Every victim must pay a
+    // lower fee rate than `tx`, and the sender's own earlier nonces are never
+    // dropped since that would strand it.
+    fn plan_eviction(
+        &self,
+        tx: &Transaction,
+        size: usize,
+    ) -> Result<Vec<([u8; 33], u64)>, TransactionError> {
+        let mut victims = Vec::new();
+        let mut dropped: HashMap<[u8; 33], usize> = HashMap::new();
+        let mut freed = 0;
+
+        while self.total_bytes - freed + size > self.max_bytes {
+            let victim = self
+                .by_sender
+                .iter()
+                .filter_map(|(sender, queue)| {
+                    let skip = dropped.get(sender).copied().unwrap_or(0);
+                    queue.values().rev().nth(skip)
+                })
+                .filter(|entry| entry.tx.sender != tx.sender || entry.tx.nonce > tx.nonce)
+                .min_by(|a, b| cmp_fee_rate(a.tx.fee, a.size, b.tx.fee, b.size));
+
+            match victim {
+                Some(entry)
+                    if cmp_fee_rate(entry.tx.fee, entry.size, tx.fee, size) == Ordering::Less =>
+                {
+                    victims.push((entry.tx.sender, entry.tx.nonce));
+                    *dropped.entry(entry.tx.sender).or_insert(0) += 1;
+                    freed += entry.size;
+                }
+                _ => return Err(TransactionError::MempoolFull),
+            }
+        }
+
+        Ok(victims)
+    }
+
+    pub fn remove(&mut self, sender: &[u8; 33], nonce: u64) -> Option<Transaction> {
+        let queue = self.by_sender.get_mut(sender)?;
+        let entry = queue.remove(&nonce)?;
+
+        if queue.is_empty() {
+            self.by_sender.remove(sender);
+        }
+        self.txids.remove(&entry.tx.txid());
+        self.total_bytes -= entry.size;
+
+        Some(entry.tx)
+    }
+
+    // Choose the most profitable transactions that fit in `max_bytes`. Each
+    // sender's transactions are taken in nonce order starting at their next
+    // nonce, so a sender's best transaction can only be picked once the ones
+    // before it have been.
+    pub fn select(
+        &self,
+        next_nonce: impl Fn(&[u8; 33]) -> u64,
+        max_bytes: usize,
+    ) -> Vec<Transaction> {
+        let mut heap: BinaryHeap<Candidate> = self
+            .by_sender
+            .iter()
+            .filter_map(|(sender, queue)| queue.get(&next_nonce(sender)))
+            .map(|entry| Candidate { entry })
+            .collect();
+
+        let mut selected = Vec::new();
+        let mut used = 0;
+
+        while let Some(Candidate { entry }) = heap.pop() {
+            // Once a sender's next transaction doesn't fit, neither can any
+            // of their later ones
+            if used + entry.size > max_bytes {
+                continue;
+            }
+
+            used += entry.size;
+            selected.push(entry.tx.clone());
+
+            let queue = &self.by_sender[&entry.tx.sender];
+            if let Some(next) = queue.get(&(entry.tx.nonce + 1)) {
+                heap.push(Candidate { entry: next });
+            }
+        }
+
+        selected
+    }
+
+    // Drop transactions whose nonce has already been used on chain
+    pub fn prune(&mut self, next_nonce: impl Fn(&[u8; 33]) -> u64) {
+        let senders: Vec<[u8; 33]> = self.by_sender.keys().copied().collect();
+
+        for sender in senders {
+            let stale: Vec<u64> = self.by_sender[&sender]
+                .range(..next_nonce(&sender))
+                .map(|(nonce, _)| *nonce)
+                .collect();
+            for nonce in stale {
+                self.remove(&sender, nonce);
+            }
+        }
+    }
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_BYTES)
+    }
+}
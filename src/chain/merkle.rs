@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Proof that a leaf is part of a merkle tree. The leaf's position decides
+// on which side each sibling is hashed in.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+// Hash one level of the tree into the next, duplicating the last node on odd levels
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
+// Compute the root of the tree built over the given leaves
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return [0; 32];
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+impl MerkleProof {
+    // Collect the sibling hashes on the path from a leaf up to the root
+    pub fn generate(leaves: &[[u8; 32]], index: usize) -> Option<Self> {
+        if index >= leaves.len() {
+            return None;
+        }
+
+        let mut siblings = Vec::new();
+        let mut level = leaves.to_vec();
+        let mut position = index;
+
+        while level.len() > 1 {
+            let sibling = level.get(position ^ 1).unwrap_or(&level[position]);
+            siblings.push(*sibling);
+
+            level = next_level(&level);
+            position /= 2;
+        }
+
+        Some(Self { index, siblings })
+    }
+
+    // Recompute the root from a leaf and check it against the expected root
+    pub fn verify(&self, leaf: [u8; 32], root: [u8; 32]) -> bool {
+        let mut hash = leaf;
+        let mut position = self.index;
+
+        for sibling in &self.siblings {
+            hash = if position & 1 == 0 {
+                hash_pair(&hash, sibling)
+            } else {
+                hash_pair(sibling, &hash)
+            };
+            position /= 2;
+        }
+
+        position == 0 && hash == root
+    }
+}
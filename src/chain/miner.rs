@@ -0,0 +1,163 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::chain::block::Block;
+use crate::chain::clock::Clock;
+
+// How many nonces a worker tries between checks of the stop flags
+const BATCH_SIZE: u64 = 1024;
+
+// Clonable handle that stops a running search, e.g. when a competing block
+// arrives from the network
+#[derive(Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+// Searches for a nonce that solves a block, splitting the nonce space
+// between worker threads. A worker that runs out of nonces bumps the block
+// timestamp to the given clock's time and starts over.
+pub struct Miner {
+    pub threads: usize,
+    // Last nonce tried before the timestamp is bumped
+    pub max_nonce: u64,
+    cancel: CancelHandle,
+    hashes: AtomicU64,
+    started: Mutex<Option<Instant>>,
+    elapsed: Mutex<Duration>,
+}
+
+impl Miner {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            max_nonce: u64::MAX,
+            cancel: CancelHandle::default(),
+            hashes: AtomicU64::new(0),
+            started: Mutex::new(None),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    // Hashes per second over the current search, or the last one if idle
+    pub fn hashrate(&self) -> f64 {
+        let elapsed = match *self.started.lock().unwrap() {
+            Some(started) => started.elapsed(),
+            None => *self.elapsed.lock().unwrap(),
+        };
+
+        if elapsed.is_zero() {
+            return 0.0;
+        }
+        self.hashes.load(Ordering::Relaxed) as f64 / elapsed.as_secs_f64()
+    }
+
+    // Search for a solution to `block`. Returns None if cancelled first,
+    // including by a cancel issued since the last search ended, e.g. while
+    // the template was being built. The flag is cleared once this search
+    // ends, so the next one starts afresh.
+    pub fn mine(&self, block: Block, clock: &dyn Clock) -> Option<Block> {
+        if self.cancel.is_cancelled() {
+            self.cancel.reset();
+            return None;
+        }
+        self.hashes.store(0, Ordering::Relaxed);
+        *self.started.lock().unwrap() = Some(Instant::now());
+
+        let found = AtomicBool::new(false);
+        let solution = Mutex::new(None);
+
+        let threads = self.threads as u64;
+        let chunk = (self.max_nonce / threads).max(1);
+
+        thread::scope(|scope| {
+            for i in 0..threads {
+                let first = (i * chunk).min(self.max_nonce);
+                let last = if i + 1 == threads {
+                    self.max_nonce
+                } else {
+                    ((i + 1) * chunk - 1).min(self.max_nonce)
+                };
+
+                let (block, found, solution) = (block.clone(), &found, &solution);
+                scope.spawn(move || self.work(block, clock, first, last, found, solution));
+            }
+        });
+
+        let started = self.started.lock().unwrap().take();
+        *self.elapsed.lock().unwrap() = started.map(|s| s.elapsed()).unwrap_or_default();
+        self.cancel.reset();
+
+        solution.into_inner().unwrap()
+    }
+
+    fn work(
+        &self,
+        mut block: Block,
+        clock: &dyn Clock,
+        first: u64,
+        last: u64,
+        found: &AtomicBool,
+        solution: &Mutex<Option<Block>>,
+    ) {
+        let mut tried = 0;
+
+        loop {
+            let mut nonce = first;
+
+            loop {
+                if tried == BATCH_SIZE {
+                    self.hashes.fetch_add(tried, Ordering::Relaxed);
+                    tried = 0;
+
+                    if found.load(Ordering::Relaxed) || self.cancel.is_cancelled() {
+                        return;
+                    }
+                }
+
+                block.nonce = nonce;
+                tried += 1;
+                if block.meets_target() {
+                    self.hashes.fetch_add(tried, Ordering::Relaxed);
+                    if !found.swap(true, Ordering::SeqCst) {
+                        *solution.lock().unwrap() = Some(block);
+                    }
+                    return;
+                }
+
+                if nonce == last {
+                    break;
+                }
+                nonce += 1;
+            }
+
+            // Nonce range exhausted, so change the header and search again
+            block.timestamp = clock.now().max(block.timestamp + 1);
+        }
+    }
+}
+
+impl Default for Miner {
+    fn default() -> Self {
+        Self::new(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use crate::chain::block::Block;
+
+// Default limit on how many orphan blocks are held at once
+pub const DEFAULT_MAX_ORPHANS: usize = 100;
+
+// Default number of seconds an orphan is kept while waiting for its parent
+pub const DEFAULT_MAX_AGE: u64 = 20 * 60;
+
+struct Orphan {
+    block: Block,
+    received: u64,
+}
+
+// Blocks whose parent hasn't arrived yet, keyed by the missing parent.
+// When the pool is full the oldest orphan is dropped to make room.
+pub struct OrphanPool {
+    pub max_orphans: usize,
+    pub max_age: u64,
+    orphans: HashMap<[u8; 32], Orphan>,
+    by_parent: HashMap<[u8; 32], Vec<[u8; 32]>>,
+}
+
+impl OrphanPool {
+    pub fn new(max_orphans: usize, max_age: u64) -> Self {
+        Self {
+            max_orphans,
+            max_age,
+            orphans: HashMap::new(),
+            by_parent: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.orphans.contains_key(hash)
+    }
+
+    // Hold a block until its parent arrives. `now` is the current time in
+    // seconds. Returns false if the block was already held.
+    pub fn insert(&mut self, block: Block, now: u64) -> bool {
+        let hash = block.hash();
+        if self.max_orphans == 0 || self.contains(&hash) {
+            return false;
+        }
+
+        self.expire(now);
+        while self.orphans.len() >= self.max_orphans {
+            let oldest = self
+                .orphans
+                .iter()
+                .min_by_key(|(_, orphan)| orphan.received)
+                .map(|(hash, _)| *hash)
+                .unwrap();
+            self.remove(&oldest);
+        }
+
+        self.by_parent
+            .entry(block.prev_hash)
+            .or_default()
+            .push(hash);
+        self.orphans.insert(
+            hash,
+            Orphan {
+                block,
+                received: now,
+            },
+        );
+        true
+    }
+
+    fn remove(&mut self, hash: &[u8; 32]) -> Option<Block> {
+        let orphan = self.orphans.remove(hash)?;
+        let parent = orphan.block.prev_hash;
+
+        if let Some(children) = self.by_parent.get_mut(&parent) {
+            children.retain(|child| child != hash);
+            if children.is_empty() {
+                self.by_parent.remove(&parent);
+            }
+        }
+
+        Some(orphan.block)
+    }
+
+    // Remove and return the blocks waiting on `parent`
+    pub fn take_children(&mut self, parent: &[u8; 32]) -> Vec<Block> {
+        let children = self.by_parent.remove(parent).unwrap_or_default();
+
+        children
+            .iter()
+            .filter_map(|hash| self.orphans.remove(hash))
+            .map(|orphan| orphan.block)
+            .collect()
+    }
+
+    // Drop every orphan built on `hash`, directly or indirectly
+    pub fn remove_descendants(&mut self, hash: &[u8; 32]) {
+        let mut parents = vec![*hash];
+
+        while let Some(parent) = parents.pop() {
+            for child in self.take_children(&parent) {
+                parents.push(child.hash());
+            }
+        }
+    }
+
+    // Drop orphans that have waited longer than `max_age`
+    pub fn expire(&mut self, now: u64) {
+        let expired: Vec<[u8; 32]> = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| now.saturating_sub(orphan.received) > self.max_age)
+            .map(|(hash, _)| *hash)
+            .collect();
+
+        for hash in expired {
+            self.remove(&hash);
+        }
+    }
+
+    // Parents that are neither known nor orphans themselves, which need to
+    // be requested from peers
+    pub fn missing_parents(&self) -> Vec<[u8; 32]> {
+        self.by_parent
+            .keys()
+            .filter(|parent| !self.orphans.contains_key(*parent))
+            .copied()
+            .collect()
+    }
+}
+
+impl Default for OrphanPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ORPHANS, DEFAULT_MAX_AGE)
+    }
+}
@@ -0,0 +1,91 @@
+use crate::chain::block::Block;
+use crate::chain::retarget::{RetargetMode, RetargetParams};
+use crate::chain::subsidy::{COIN, SubsidySchedule};
+use crate::chain::target::U256;
+
+// Consensus settings that every node on a network must agree on. The
+// genesis block is built from these, so it is identical on every node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainParams {
+    pub name: &'static str,
+    // Prefix identifying the network's messages, so nodes on different
+    // networks don't talk to each other
+    pub magic: [u8; 4],
+    pub genesis_timestamp: u64,
+    pub genesis_nonce: u64,
+    // Balances credited by the genesis block
+    pub premine: Vec<([u8; 33], u64)>,
+    // Compact target the genesis block and its first children must meet
+    pub initial_bits: u32,
+    pub subsidy: SubsidySchedule,
+    pub retarget: RetargetParams,
+    // Hashes of account state snapshots at known heights, trusted when a
+    // node bootstraps from a snapshot without an operator-supplied hash
+    pub snapshots: Vec<(u64, [u8; 32])>,
+}
+
+impl ChainParams {
+    pub fn mainnet() -> Self {
+        Self {
+            name: "mainnet",
+            magic: [0xf3, 0xb1, 0xc7, 0x9a],
+            genesis_timestamp: 1_735_689_600,
+            genesis_nonce: 256_283,
+            premine: Vec::new(),
+            initial_bits: 0x1f00ffff,
+            subsidy: SubsidySchedule::default(),
+            retarget: RetargetParams {
+                pow_limit: U256::from_compact(0x1f00ffff).unwrap(),
+                ..RetargetParams::default()
+            },
+            snapshots: Vec::new(),
+        }
+    }
+
+    pub fn testnet() -> Self {
+        Self {
+            name: "testnet",
+            magic: [0x0b, 0x2e, 0x6d, 0x14],
+            genesis_timestamp: 1_738_368_000,
+            genesis_nonce: 5122,
+            premine: Vec::new(),
+            initial_bits: 0x1f0fffff,
+            subsidy: SubsidySchedule::default(),
+            retarget: RetargetParams {
+                mode: RetargetMode::Lwma { window: 45 },
+                pow_limit: U256::from_compact(0x1f0fffff).unwrap(),
+                ..RetargetParams::default()
+            },
+            snapshots: Vec::new(),
+        }
+    }
+
+    // Local network for testing, where blocks can be mined instantly
+    pub fn regtest() -> Self {
+        Self {
+            name: "regtest",
+            magic: [0xfa, 0xbf, 0xb5, 0xda],
+            genesis_timestamp: 1_704_067_200,
+            genesis_nonce: 1,
+            premine: Vec::new(),
+            initial_bits: 0x207fffff,
+            subsidy: SubsidySchedule::new(50 * COIN, 150),
+            retarget: RetargetParams::default(),
+            snapshots: Vec::new(),
+        }
+    }
+
+    // Look up a preset by name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mainnet" => Some(Self::mainnet()),
+            "testnet" => Some(Self::testnet()),
+            "regtest" => Some(Self::regtest()),
+            _ => None,
+        }
+    }
+
+    pub fn genesis(&self) -> Block {
+        Block::create_genesis(self)
+    }
+}
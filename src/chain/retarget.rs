@@ -0,0 +1,110 @@
+use crate::chain::block::BlockHeader;
+use crate::chain::target::U256;
+
+// How the target is recalculated as blocks are added
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RetargetMode {
+    // Bitcoin-style: every `interval` blocks, scale the target by how long
+    // the last window took compared to the expected time
+    Window { interval: u64 },
+    // Linearly weighted moving average over the last `window` solve times,
+    // recalculated every block
+    Lwma { window: u64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetargetParams {
+    pub mode: RetargetMode,
+    // Desired seconds between blocks
+    pub target_spacing: u64,
+    // Easiest target a block may have
+    pub pow_limit: U256,
+}
+
+impl RetargetParams {
+    // Compact target for the block at `height`, given a way to look up its
+    // ancestors by height. Only ancestors below `height` are read.
+    pub fn next_bits<'a>(&self, height: u64, block_at: impl Fn(u64) -> &'a BlockHeader) -> u32 {
+        let parent = block_at(height - 1);
+
+        match self.mode {
+            RetargetMode::Window { interval } => {
+                if !height.is_multiple_of(interval) {
+                    return parent.bits;
+                }
+                self.window_bits(parent, block_at(height - interval), interval)
+            }
+            RetargetMode::Lwma { window } => {
+                if window == 0 || height <= window {
+                    return parent.bits;
+                }
+                self.lwma_bits(height, window, block_at)
+            }
+        }
+    }
+
+    fn window_bits(&self, parent: &BlockHeader, first: &BlockHeader, interval: u64) -> u32 {
+        let expected = interval * self.target_spacing;
+
+        // Limit each adjustment to a factor of four either way
+        let actual = parent
+            .timestamp
+            .saturating_sub(first.timestamp)
+            .clamp(expected / 4, expected * 4);
+
+        let target = U256::from_compact(parent.bits).unwrap_or(self.pow_limit);
+        self.scale(target, actual, expected)
+    }
+
+    fn lwma_bits<'a>(
+        &self,
+        height: u64,
+        window: u64,
+        block_at: impl Fn(u64) -> &'a BlockHeader,
+    ) -> u32 {
+        let spacing = self.target_spacing as i64;
+
+        let mut weighted_time: i64 = 0;
+        let mut average_target = U256::ZERO;
+
+        for (weight, h) in (height - window..height).enumerate() {
+            let (block, prev) = (block_at(h), block_at(h - 1));
+
+            // Timestamps aren't monotonic, so bound each solve time to stop
+            // a single bad timestamp from swinging the target
+            let solve_time =
+                (block.timestamp as i64 - prev.timestamp as i64).clamp(-6 * spacing, 6 * spacing);
+            weighted_time += (weight as i64 + 1) * solve_time;
+
+            let target = U256::from_compact(block.bits).unwrap_or(self.pow_limit);
+            average_target = average_target.saturating_add(target.div_u64(window));
+        }
+
+        let expected = (window * (window + 1) / 2) * self.target_spacing;
+        let actual = (weighted_time.max(0) as u64).max(expected / 10);
+
+        self.scale(average_target, actual, expected)
+    }
+
+    // target * actual / expected, capped at the pow limit
+    fn scale(&self, target: U256, actual: u64, expected: u64) -> u32 {
+        let scaled = match target.checked_mul_u64(actual) {
+            Some(product) => product.div_u64(expected),
+            None => target
+                .div_u64(expected)
+                .checked_mul_u64(actual)
+                .unwrap_or(U256::MAX),
+        };
+        scaled.min(self.pow_limit).to_compact()
+    }
+}
+
+impl Default for RetargetParams {
+    fn default() -> Self {
+        Self {
+            mode: RetargetMode::Window { interval: 2016 },
+            target_spacing: 600,
+            pow_limit: U256::from_compact(0x207fffff).unwrap(),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::chain::blockchain::BlockError;
+use crate::chain::encoding::{self, EncodingError, byte_array};
+use crate::chain::state::AccountState;
+use crate::chain::storage::StorageError;
+
+// Metadata key holding the height and hash of the snapshot a chain was
+// bootstrapped from
+pub const SNAPSHOT: &str = "snapshot";
+
+// Length of the hash appended to a snapshot file
+const CHECKSUM_SIZE: usize = 32;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    #[serde(with = "byte_array")]
+    pub account: [u8; 33],
+    pub state: AccountState,
+}
+
+// Every account's state after the block at `height` was connected. Entries
+// are sorted by account, so the same state always has the same hash.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub height: u64,
+    pub block_hash: [u8; 32],
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Encoding(EncodingError),
+    Storage(StorageError),
+    BadChecksum,
+    // The chain has no state for that height, either because it's past the
+    // tip or before the point the chain was bootstrapped from
+    UnavailableHeight(u64),
+    // Neither the operator nor the chain parameters vouch for the snapshot
+    Untrusted,
+    HashMismatch { expected: [u8; 32], found: [u8; 32] },
+    NotEmpty,
+    InvalidHeaders(usize),
+    InvalidHeader { height: u64, error: BlockError },
+    BlockMismatch,
+    StateRootMismatch,
+}
+
+impl Snapshot {
+    pub fn new(
+        height: u64,
+        block_hash: [u8; 32],
+        accounts: HashMap<[u8; 33], AccountState>,
+    ) -> Self {
+        let mut entries: Vec<SnapshotEntry> = accounts
+            .into_iter()
+            .map(|(account, state)| SnapshotEntry { account, state })
+            .collect();
+        entries.sort_unstable_by_key(|entry| entry.account);
+
+        Self {
+            height,
+            block_hash,
+            entries,
+        }
+    }
+
+    // Double SHA-256 of the encoded snapshot. This is the value operators
+    // compare and chain parameters commit to.
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::digest(Sha256::digest(encoding::encode(self))).into()
+    }
+
+    pub fn accounts(&self) -> HashMap<[u8; 33], AccountState> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.account, entry.state))
+            .collect()
+    }
+
+    // The encoded snapshot followed by its hash
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = encoding::encode(self);
+        bytes.extend_from_slice(&self.hash());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let split = bytes
+            .len()
+            .checked_sub(CHECKSUM_SIZE)
+            .ok_or(SnapshotError::BadChecksum)?;
+        let (payload, checksum) = bytes.split_at(split);
+
+        if Sha256::digest(Sha256::digest(payload)).as_slice() != checksum {
+            return Err(SnapshotError::BadChecksum);
+        }
+        Ok(encoding::decode(payload)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<EncodingError> for SnapshotError {
+    fn from(e: EncodingError) -> Self {
+        SnapshotError::Encoding(e)
+    }
+}
+
+impl From<StorageError> for SnapshotError {
+    fn from(e: StorageError) -> Self {
+        SnapshotError::Storage(e)
+    }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot I/O error: {}", e),
+            SnapshotError::Encoding(e) => write!(f, "snapshot is malformed: {}", e),
+            SnapshotError::Storage(e) => write!(f, "{}", e),
+            SnapshotError::BadChecksum => write!(f, "snapshot checksum does not match"),
+            SnapshotError::UnavailableHeight(height) => {
+                write!(f, "no account state is available at height {}", height)
+            }
+            SnapshotError::Untrusted => {
+                write!(f, "no trusted hash is known for a snapshot at this height")
+            }
+            SnapshotError::HashMismatch { expected, found } => write!(
+                f,
+                "snapshot hash {} does not match the trusted hash {}",
+                hex::encode(found),
+                hex::encode(expected)
+            ),
+            SnapshotError::NotEmpty => write!(f, "store already holds a chain"),
+            SnapshotError::InvalidHeaders(count) => write!(
+                f,
+                "expected headers from genesis to the snapshot block, found {}",
+                count
+            ),
+            SnapshotError::InvalidHeader { height, error } => {
+                write!(f, "header {} is invalid: {}", height, error)
+            }
+            SnapshotError::BlockMismatch => {
+                write!(f, "snapshot block is not the last of the headers")
+            }
+            SnapshotError::StateRootMismatch => {
+                write!(
+                    f,
+                    "snapshot does not match the state root in its block's header"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
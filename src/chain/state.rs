@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+// What the ledger records for each account
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountState {
+    pub balance: u64,
+    // Nonce the account's next transaction must carry
+    pub nonce: u64,
+}
@@ -0,0 +1,336 @@
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::chain::block::BlockHeader;
+use crate::chain::state::AccountState;
+
+// Domain prefixes, so a leaf hash can never pass for an inner node
+const LEAF: u8 = 0x00;
+const NODE: u8 = 0x01;
+
+// Sparse Merkle tree over every account's state. An account's leaf sits at
+// the path given by the SHA-256 of its public key. An empty subtree hashes
+// to zero and a subtree holding a single account hashes to that account's
+// leaf, so only the populated parts of the 256-level tree are ever hashed.
+// Inner hashes are cached, so a change only rehashes the nodes on its path.
+#[derive(Clone, Debug, Default)]
+pub struct StateTree {
+    // Accounts by path, in path order
+    leaves: BTreeMap<[u8; 32], Leaf>,
+    // Hash of every subtree holding two or more accounts, by depth and the
+    // path prefix leading to it
+    nodes: HashMap<(usize, [u8; 32]), [u8; 32]>,
+}
+
+#[derive(Clone, Debug)]
+struct Leaf {
+    account: [u8; 33],
+    state: AccountState,
+    hash: [u8; 32],
+}
+
+// Proof of an account's state, or of its absence, against a state root
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountProof {
+    pub account: [u8; 33],
+    // None if the account has no entry
+    pub state: Option<AccountState>,
+    // Hashes of the sibling subtrees from the root down to the account's leaf
+    pub siblings: Vec<[u8; 32]>,
+    // For an absent account, the single other account found where its path
+    // ends, if the subtree there isn't empty
+    pub other: Option<([u8; 33], AccountState)>,
+}
+
+pub fn path(account: &[u8; 33]) -> [u8; 32] {
+    Sha256::digest(account).into()
+}
+
+pub fn leaf_hash(account: &[u8; 33], state: &AccountState) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF]);
+    hasher.update(path(account));
+    hasher.update(account);
+    hasher.update(state.balance.to_le_bytes());
+    hasher.update(state.nonce.to_le_bytes());
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+// Whether the path turns right at `depth`
+fn bit(path: &[u8; 32], depth: usize) -> bool {
+    path[depth / 8] >> (7 - depth % 8) & 1 == 1
+}
+
+// The first `depth` bits of a path, with the rest cleared
+fn prefix(path: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut prefix = [0; 32];
+    let full = depth / 8;
+    prefix[..full].copy_from_slice(&path[..full]);
+    if !depth.is_multiple_of(8) {
+        prefix[full] = path[full] & (0xff << (8 - depth % 8));
+    }
+    prefix
+}
+
+// Prefix of the right child of the subtree at `depth`
+fn right_child(prefix: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut child = *prefix;
+    child[depth / 8] |= 0x80 >> (depth % 8);
+    child
+}
+
+// Last path in the subtree at `depth`
+fn last_path(prefix: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut last = *prefix;
+    let full = depth / 8;
+    if !depth.is_multiple_of(8) {
+        last[full] |= 0xff >> (depth % 8);
+        last[full + 1..].fill(0xff);
+    } else {
+        last[full..].fill(0xff);
+    }
+    last
+}
+
+// Hash the subtree at `depth` holding `leaves`, which are sorted by path,
+// caching the hash of every inner node
+fn build(
+    nodes: &mut HashMap<(usize, [u8; 32]), [u8; 32]>,
+    leaves: &[([u8; 32], [u8; 32])],
+    depth: usize,
+) -> [u8; 32] {
+    match leaves {
+        [] => [0; 32],
+        [(_, leaf)] => *leaf,
+        _ => {
+            let split = leaves.partition_point(|(path, _)| !bit(path, depth));
+            let hash = node_hash(
+                &build(nodes, &leaves[..split], depth + 1),
+                &build(nodes, &leaves[split..], depth + 1),
+            );
+            nodes.insert((depth, prefix(&leaves[0].0, depth)), hash);
+            hash
+        }
+    }
+}
+
+impl StateTree {
+    pub fn new(accounts: impl IntoIterator<Item = ([u8; 33], AccountState)>) -> Self {
+        let mut tree = Self::default();
+        for (account, state) in accounts {
+            let leaf = Leaf {
+                account,
+                state,
+                hash: leaf_hash(&account, &state),
+            };
+            tree.leaves.insert(path(&account), leaf);
+        }
+
+        let hashes: Vec<_> = tree
+            .leaves
+            .iter()
+            .map(|(path, leaf)| (*path, leaf.hash))
+            .collect();
+        build(&mut tree.nodes, &hashes, 0);
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn insert(&mut self, account: &[u8; 33], state: &AccountState) {
+        let leaf = Leaf {
+            account: *account,
+            state: *state,
+            hash: leaf_hash(account, state),
+        };
+        self.leaves.insert(path(account), leaf);
+        self.update(&path(account));
+    }
+
+    pub fn get(&self, account: &[u8; 33]) -> Option<AccountState> {
+        self.leaves.get(&path(account)).map(|leaf| leaf.state)
+    }
+
+    pub fn remove(&mut self, account: &[u8; 33]) {
+        if self.leaves.remove(&path(account)).is_some() {
+            self.update(&path(account));
+        }
+    }
+
+    // Leaves in the subtree at `depth` under `prefix`
+    fn subtree(&self, depth: usize, prefix: &[u8; 32]) -> impl Iterator<Item = (&[u8; 32], &Leaf)> {
+        self.leaves.range(*prefix..=last_path(prefix, depth))
+    }
+
+    fn has_inner_node(&self, depth: usize, prefix: &[u8; 32]) -> bool {
+        self.subtree(depth, prefix).nth(1).is_some()
+    }
+
+    fn subtree_hash(&self, depth: usize, prefix: &[u8; 32]) -> [u8; 32] {
+        let mut leaves = self.subtree(depth, prefix);
+        match (leaves.next(), leaves.next()) {
+            (None, _) => [0; 32],
+            (Some((_, leaf)), None) => leaf.hash,
+            _ => self.nodes[&(depth, *prefix)],
+        }
+    }
+
+    // Rehash the inner nodes above the leaf at `path` after it changed, and
+    // drop the ones left holding fewer than two accounts
+    fn update(&mut self, path: &[u8; 32]) {
+        let mut depth = 0;
+        while self.has_inner_node(depth, &prefix(path, depth)) {
+            depth += 1;
+        }
+
+        let mut stale = depth;
+        while self.nodes.remove(&(stale, prefix(path, stale))).is_some() {
+            stale += 1;
+        }
+
+        for depth in (0..depth).rev() {
+            let left = prefix(path, depth);
+            let right = right_child(&left, depth);
+            let hash = node_hash(
+                &self.subtree_hash(depth + 1, &left),
+                &self.subtree_hash(depth + 1, &right),
+            );
+            self.nodes.insert((depth, left), hash);
+        }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.subtree_hash(0, &[0; 32])
+    }
+
+    // Root after applying a set of account changes, leaving the tree as is.
+    // Only the subtrees the changes fall in are rehashed.
+    pub fn root_after(&self, update: &HashMap<[u8; 33], AccountState>) -> [u8; 32] {
+        let mut changed: Vec<_> = update
+            .iter()
+            .map(|(account, state)| (path(account), leaf_hash(account, state)))
+            .collect();
+        changed.sort_unstable();
+        self.root_with(0, &[0; 32], &changed)
+    }
+
+    // Hash of the subtree at `depth` once the `changed` leaves in it, sorted
+    // by path, are put in
+    fn root_with(
+        &self,
+        depth: usize,
+        prefix: &[u8; 32],
+        changed: &[([u8; 32], [u8; 32])],
+    ) -> [u8; 32] {
+        if changed.is_empty() {
+            return self.subtree_hash(depth, prefix);
+        }
+
+        // A single changed leaf with no other account beside it is the
+        // whole subtree
+        if let [(path, hash)] = changed
+            && self.subtree(depth, prefix).all(|(other, _)| other == path)
+        {
+            return *hash;
+        }
+
+        let split = changed.partition_point(|(path, _)| !bit(path, depth));
+        node_hash(
+            &self.root_with(depth + 1, prefix, &changed[..split]),
+            &self.root_with(depth + 1, &right_child(prefix, depth), &changed[split..]),
+        )
+    }
+
+    // Prove an account's state, or that it has no entry
+    pub fn prove(&self, account: &[u8; 33]) -> AccountProof {
+        let target = path(account);
+
+        let mut siblings = Vec::new();
+        let mut depth = 0;
+        while self.has_inner_node(depth, &prefix(&target, depth)) {
+            let left = prefix(&target, depth);
+            let sibling = if bit(&target, depth) {
+                left
+            } else {
+                right_child(&left, depth)
+            };
+            siblings.push(self.subtree_hash(depth + 1, &sibling));
+            depth += 1;
+        }
+
+        let other = match self.subtree(depth, &prefix(&target, depth)).next() {
+            Some((path, leaf)) if *path != target => Some((leaf.account, leaf.state)),
+            _ => None,
+        };
+
+        AccountProof {
+            account: *account,
+            state: self.get(account),
+            siblings,
+            other,
+        }
+    }
+}
+
+impl AccountProof {
+    pub fn balance(&self) -> u64 {
+        self.state.map_or(0, |state| state.balance)
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.state.map_or(0, |state| state.nonce)
+    }
+
+    // Check the proof against the state root committed to by a header
+    pub fn verify(&self, header: &BlockHeader) -> bool {
+        self.verify_root(&header.state_root)
+    }
+
+    pub fn verify_root(&self, root: &[u8; 32]) -> bool {
+        let target = path(&self.account);
+        if self.siblings.len() > 256 {
+            return false;
+        }
+
+        let mut hash = match (&self.state, &self.other) {
+            (Some(state), None) => leaf_hash(&self.account, state),
+            (None, None) => [0; 32],
+            (None, Some((other, state))) => {
+                // The other account must sit on the same path down to where
+                // the proof ends, or it proves nothing about this one
+                let other_path = path(other);
+                let shares_path = (0..self.siblings.len())
+                    .all(|depth| bit(&other_path, depth) == bit(&target, depth));
+                if other_path == target || !shares_path {
+                    return false;
+                }
+                leaf_hash(other, state)
+            }
+            (Some(_), Some(_)) => return false,
+        };
+
+        for (depth, sibling) in self.siblings.iter().enumerate().rev() {
+            hash = if bit(&target, depth) {
+                node_hash(sibling, &hash)
+            } else {
+                node_hash(&hash, sibling)
+            };
+        }
+
+        hash == *root
+    }
+}
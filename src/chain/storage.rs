@@ -0,0 +1,380 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::chain::block::Block;
+use crate::chain::encoding::{self, EncodingError};
+
+// Default size at which a new segment file is started
+pub const DEFAULT_SEGMENT_SIZE: u64 = 128 * 1024 * 1024;
+
+// Magic, payload length and checksum in front of every record
+const HEADER_SIZE: usize = 4 + 4 + 4;
+
+// Where a block's record starts and how long its payload is
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockLocation {
+    pub segment: u32,
+    pub offset: u64,
+    pub len: u32,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    Corrupt { segment: u32, offset: u64 },
+    Database(String),
+    // The database is already open, in this process or another one
+    Locked,
+    Encoding(EncodingError),
+    GenesisMismatch,
+    UnknownTip,
+    // The body of an active block was pruned or came before a snapshot
+    MissingBody { height: u64 },
+    // An active block has no undo data to disconnect it with
+    MissingUndo { height: u64 },
+}
+
+// Append-only block storage split across numbered segment files. Each
+// record is the network magic, the payload length, a checksum and the
+// encoded block. The index from hash and height to record lives in the
+// database, so opening the store doesn't read the segments back. A record
+// past the end of the index, as left by a crash part way through an
+// append, is cut off on open.
+pub struct BlockStore {
+    pub dir: PathBuf,
+    pub magic: [u8; 4],
+    pub segment_size: u64,
+    index: sled::Tree,
+    segment: u32,
+    segment_len: u64,
+}
+
+// Key prefixes in the index
+const LOCATION: u8 = b'l';
+const HEIGHT: u8 = b'h';
+// Number of indexed blocks in a segment
+const LIVE: u8 = b'n';
+// Segment being appended to and the end of its last indexed record
+const TAIL: &[u8] = b"t";
+
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    let hash = Sha256::digest(Sha256::digest(payload));
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+fn location_key(hash: &[u8; 32]) -> Vec<u8> {
+    let mut key = vec![LOCATION];
+    key.extend_from_slice(hash);
+    key
+}
+
+// Big-endian heights keep a height's hashes together
+fn height_key(height: u64, hash: &[u8; 32]) -> Vec<u8> {
+    let mut key = vec![HEIGHT];
+    key.extend_from_slice(&height.to_be_bytes());
+    key.extend_from_slice(hash);
+    key
+}
+
+fn live_key(segment: u32) -> Vec<u8> {
+    let mut key = vec![LIVE];
+    key.extend_from_slice(&segment.to_be_bytes());
+    key
+}
+
+impl BlockStore {
+    // Open the store in `dir`, creating it if needed, with its index in
+    // `index`
+    pub fn open(
+        dir: impl AsRef<Path>,
+        magic: [u8; 4],
+        index: sled::Tree,
+    ) -> Result<Self, StorageError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let tail = index.get(TAIL)?;
+        let (segment, segment_len) = match &tail {
+            Some(bytes) => encoding::decode(bytes)?,
+            None => (0, 0),
+        };
+        let store = Self {
+            dir,
+            magic,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            index,
+            segment,
+            segment_len,
+        };
+
+        let segments = store.segments()?;
+        if tail.is_none() && !segments.is_empty() {
+            return Err(StorageError::Database("block index is missing".to_string()));
+        }
+
+        // Only an append that never made it into the index writes past the
+        // tail, and a segment with no indexed blocks left was being deleted
+        // when the node stopped
+        for number in segments {
+            let path = store.segment_path(number);
+            if number == segment {
+                let file = OpenOptions::new().write(true).open(&path)?;
+                if file.metadata()?.len() > segment_len {
+                    file.set_len(segment_len)?;
+                }
+            } else if number > segment || store.live(number)? == 0 {
+                fs::remove_file(&path)?;
+            }
+        }
+
+        Ok(store)
+    }
+
+    fn segment_path(&self, segment: u32) -> PathBuf {
+        self.dir.join(format!("blk{:05}.dat", segment))
+    }
+
+    // Numbers of the existing segment files, in order
+    fn segments(&self) -> Result<Vec<u32>, StorageError> {
+        let mut segments = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let number = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("blk"))
+                .and_then(|name| name.strip_suffix(".dat"))
+                .and_then(|number| number.parse().ok());
+            if let Some(number) = number {
+                segments.push(number);
+            }
+        }
+
+        segments.sort_unstable();
+        Ok(segments)
+    }
+
+    // Number of indexed blocks in a segment
+    fn live(&self, segment: u32) -> Result<u64, StorageError> {
+        match self.index.get(live_key(segment))? {
+            Some(bytes) => Ok(encoding::decode(&bytes)?),
+            None => Ok(0),
+        }
+    }
+
+    // Location and height of an indexed block
+    fn entry(&self, hash: &[u8; 32]) -> Result<Option<(BlockLocation, u64)>, StorageError> {
+        match self.index.get(location_key(hash))? {
+            Some(bytes) => Ok(Some(encoding::decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn read_record(&self, record: &[u8]) -> Option<Block> {
+        if record.len() < HEADER_SIZE || record[0..4] != self.magic {
+            return None;
+        }
+
+        let len = u32::from_le_bytes(record[4..8].try_into().unwrap()) as usize;
+        let payload = record.get(HEADER_SIZE..HEADER_SIZE + len)?;
+        if record[8..12] != checksum(payload) {
+            return None;
+        }
+        Block::decode(payload).ok()
+    }
+
+    // Number of indexed blocks. This walks the index, so it's meant for
+    // checks rather than hot paths.
+    pub fn len(&self) -> usize {
+        self.index.scan_prefix([LOCATION]).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> Result<bool, StorageError> {
+        Ok(self.index.contains_key(location_key(hash))?)
+    }
+
+    pub fn location(&self, hash: &[u8; 32]) -> Result<Option<BlockLocation>, StorageError> {
+        Ok(self.entry(hash)?.map(|(location, _)| location))
+    }
+
+    // Hashes of every stored block at `height`, on any branch
+    pub fn at_height(&self, height: u64) -> Result<Vec<[u8; 32]>, StorageError> {
+        let mut prefix = vec![HEIGHT];
+        prefix.extend_from_slice(&height.to_be_bytes());
+
+        self.index
+            .scan_prefix(&prefix)
+            .map(|entry| {
+                entry?.0[prefix.len()..]
+                    .try_into()
+                    .map_err(|_| StorageError::Database("malformed height key".to_string()))
+            })
+            .collect()
+    }
+
+    // Write a block to the end of the current segment, or a new one if it
+    // would grow past `segment_size`. The record is synced to disk before
+    // it is indexed.
+    pub fn append(&mut self, block: &Block) -> Result<BlockLocation, StorageError> {
+        let hash = block.hash();
+        if let Some(location) = self.location(&hash)? {
+            return Ok(location);
+        }
+
+        let payload = block.encode();
+        let mut record = Vec::with_capacity(HEADER_SIZE + payload.len());
+        record.extend_from_slice(&self.magic);
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&checksum(&payload));
+        record.extend_from_slice(&payload);
+
+        if self.segment_len > 0 && self.segment_len + record.len() as u64 > self.segment_size {
+            self.segment += 1;
+            self.segment_len = 0;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.segment_path(self.segment))?;
+
+        if let Err(e) = file.write_all(&record).and_then(|_| file.sync_data()) {
+            // Cut off whatever part of the record made it to disk so the
+            // next append doesn't land after garbage
+            let _ = file.set_len(self.segment_len);
+            return Err(e.into());
+        }
+
+        let location = BlockLocation {
+            segment: self.segment,
+            offset: self.segment_len,
+            len: payload.len() as u32,
+        };
+        let tail = (self.segment, self.segment_len + record.len() as u64);
+
+        let mut batch = sled::Batch::default();
+        batch.insert(
+            location_key(&hash),
+            encoding::encode(&(location, block.index)),
+        );
+        batch.insert(height_key(block.index, &hash), &[]);
+        batch.insert(
+            live_key(self.segment),
+            encoding::encode(&(self.live(self.segment)? + 1)),
+        );
+        batch.insert(TAIL, encoding::encode(&tail));
+        self.index.apply_batch(batch)?;
+        self.segment_len = tail.1;
+
+        Ok(location)
+    }
+
+    // Drop a block from the index. Records can't be cut out of the middle of
+    // a segment, so the space is only reclaimed once every block in a
+    // segment has been removed, when the whole file is deleted. The segment
+    // being appended to is never deleted.
+    pub fn remove(&mut self, hash: &[u8; 32]) -> Result<(), StorageError> {
+        let (location, height) = match self.entry(hash)? {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+
+        let live = self.live(location.segment)?.saturating_sub(1);
+        let delete = live == 0 && location.segment != self.segment;
+
+        let mut batch = sled::Batch::default();
+        batch.remove(location_key(hash));
+        batch.remove(height_key(height, hash));
+        if delete {
+            batch.remove(live_key(location.segment));
+        } else {
+            batch.insert(live_key(location.segment), encoding::encode(&live));
+        }
+        self.index.apply_batch(batch)?;
+
+        if delete {
+            fs::remove_file(self.segment_path(location.segment))?;
+        }
+        Ok(())
+    }
+
+    // Segment files currently on disk
+    pub fn segment_count(&self) -> Result<usize, StorageError> {
+        Ok(self.segments()?.len())
+    }
+
+    // Read a block back from disk, checking it against the record's
+    // checksum
+    pub fn get(&self, hash: &[u8; 32]) -> Result<Option<Block>, StorageError> {
+        let location = match self.location(hash)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let corrupt = StorageError::Corrupt {
+            segment: location.segment,
+            offset: location.offset,
+        };
+
+        let mut file = File::open(self.segment_path(location.segment))?;
+        file.seek(SeekFrom::Start(location.offset))?;
+
+        let mut record = vec![0; HEADER_SIZE + location.len as usize];
+        file.read_exact(&mut record)?;
+
+        self.read_record(&record).map(Some).ok_or(corrupt)
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+impl From<EncodingError> for StorageError {
+    fn from(e: EncodingError) -> Self {
+        StorageError::Encoding(e)
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "storage I/O error: {}", e),
+            StorageError::Corrupt { segment, offset } => {
+                write!(
+                    f,
+                    "corrupt record in segment {} at offset {}",
+                    segment, offset
+                )
+            }
+            StorageError::Database(e) => write!(f, "database error: {}", e),
+            StorageError::Locked => write!(f, "database is in use by another store"),
+            StorageError::Encoding(e) => write!(f, "stored value is malformed: {}", e),
+            StorageError::GenesisMismatch => {
+                write!(f, "stored chain starts with a different genesis block")
+            }
+            StorageError::UnknownTip => write!(f, "stored best tip is not a known block"),
+            StorageError::MissingBody { height } => {
+                write!(f, "body of the block at height {} is not stored", height)
+            }
+            StorageError::MissingUndo { height } => {
+                write!(
+                    f,
+                    "undo data for the block at height {} is not stored",
+                    height
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
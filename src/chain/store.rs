@@ -0,0 +1,235 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::chain::block::{Block, BlockHeader};
+use crate::chain::index::TxLocation;
+use crate::chain::state::AccountState;
+use crate::chain::storage::StorageError;
+use crate::chain::undo::BlockUndo;
+
+// Metadata key holding the hash of the active chain's tip
+pub const BEST_TIP: &str = "best_tip";
+
+// Metadata key holding the hashes of blocks that failed to connect
+pub const INVALID: &str = "invalid";
+
+// Metadata keys holding how many recent blocks a pruned node keeps, and the
+// height up to which bodies and undo data have been deleted
+pub const PRUNE_DEPTH: &str = "prune_depth";
+pub const PRUNED_HEIGHT: &str = "pruned_height";
+
+pub enum WriteOp {
+    // Store a block's header and body
+    PutBlock(Block),
+    // Delete a block's body, keeping its header
+    DeleteBlock([u8; 32]),
+    PutHeader(BlockHeader),
+    PutAccount([u8; 33], AccountState),
+    DeleteAccount([u8; 33]),
+    PutUndo([u8; 32], BlockUndo),
+    DeleteUndo([u8; 32]),
+    PutMeta(String, Vec<u8>),
+    PutBlockHeight([u8; 32], u64),
+    DeleteBlockHeight([u8; 32]),
+    PutTxLocation([u8; 32], TxLocation),
+    DeleteTxLocation([u8; 32]),
+    // Entries are keyed by account and location, so history comes back in
+    // chain order
+    PutAddressTx([u8; 33], [u8; 32], TxLocation),
+    DeleteAddressTx([u8; 33], TxLocation),
+}
+
+// Changes written together, so a crash never leaves a block half connected
+#[derive(Default)]
+pub struct WriteBatch {
+    pub ops: Vec<WriteOp>,
+}
+
+impl WriteBatch {
+    pub fn push(&mut self, op: WriteOp) {
+        self.ops.push(op);
+    }
+}
+
+// Everything `Blockchain` keeps: blocks and headers by hash, account state,
+// undo data for connected blocks and metadata such as the best tip
+pub trait ChainStore {
+    fn get_block(&self, hash: &[u8; 32]) -> Result<Option<Block>, StorageError>;
+
+    fn get_header(&self, hash: &[u8; 32]) -> Result<Option<BlockHeader>, StorageError>;
+
+    // Every stored header, in no particular order
+    fn headers(&self) -> Result<Vec<BlockHeader>, StorageError>;
+
+    fn get_account(&self, account: &[u8; 33]) -> Result<Option<AccountState>, StorageError>;
+
+    // Every account with an entry, in no particular order
+    fn accounts(&self) -> Result<Vec<([u8; 33], AccountState)>, StorageError>;
+
+    fn get_undo(&self, hash: &[u8; 32]) -> Result<Option<BlockUndo>, StorageError>;
+
+    fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+
+    // Height of a block on the active chain, from the block index
+    fn get_block_height(&self, hash: &[u8; 32]) -> Result<Option<u64>, StorageError>;
+
+    fn get_tx_location(&self, txid: &[u8; 32]) -> Result<Option<TxLocation>, StorageError>;
+
+    // Transactions touching an account, oldest first, from the address index
+    fn address_txs(&self, account: &[u8; 33]) -> Result<Vec<([u8; 32], TxLocation)>, StorageError>;
+
+    // Apply every change in the batch, or none of them
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StorageError>;
+
+    fn put_block(&mut self, block: Block) -> Result<(), StorageError> {
+        self.write(WriteBatch {
+            ops: vec![WriteOp::PutBlock(block)],
+        })
+    }
+
+    fn put_header(&mut self, header: BlockHeader) -> Result<(), StorageError> {
+        self.write(WriteBatch {
+            ops: vec![WriteOp::PutHeader(header)],
+        })
+    }
+
+    fn put_account(&mut self, account: [u8; 33], state: AccountState) -> Result<(), StorageError> {
+        self.write(WriteBatch {
+            ops: vec![WriteOp::PutAccount(account, state)],
+        })
+    }
+
+    fn put_undo(&mut self, hash: [u8; 32], undo: BlockUndo) -> Result<(), StorageError> {
+        self.write(WriteBatch {
+            ops: vec![WriteOp::PutUndo(hash, undo)],
+        })
+    }
+
+    fn put_meta(&mut self, key: &str, value: Vec<u8>) -> Result<(), StorageError> {
+        self.write(WriteBatch {
+            ops: vec![WriteOp::PutMeta(key.to_string(), value)],
+        })
+    }
+}
+
+// An account's indexed transactions by height and position
+type AddressHistory = BTreeMap<(u64, u32), ([u8; 32], TxLocation)>;
+
+// Keeps everything in memory, for tests and throwaway chains
+#[derive(Default)]
+pub struct MemoryStore {
+    blocks: HashMap<[u8; 32], Block>,
+    headers: HashMap<[u8; 32], BlockHeader>,
+    accounts: HashMap<[u8; 33], AccountState>,
+    undo: HashMap<[u8; 32], BlockUndo>,
+    meta: HashMap<String, Vec<u8>>,
+    block_heights: HashMap<[u8; 32], u64>,
+    tx_locations: HashMap<[u8; 32], TxLocation>,
+    address_txs: HashMap<[u8; 33], AddressHistory>,
+}
+
+impl ChainStore for MemoryStore {
+    fn get_block(&self, hash: &[u8; 32]) -> Result<Option<Block>, StorageError> {
+        Ok(self.blocks.get(hash).cloned())
+    }
+
+    fn get_header(&self, hash: &[u8; 32]) -> Result<Option<BlockHeader>, StorageError> {
+        Ok(self.headers.get(hash).cloned())
+    }
+
+    fn headers(&self) -> Result<Vec<BlockHeader>, StorageError> {
+        Ok(self.headers.values().cloned().collect())
+    }
+
+    fn get_account(&self, account: &[u8; 33]) -> Result<Option<AccountState>, StorageError> {
+        Ok(self.accounts.get(account).copied())
+    }
+
+    fn accounts(&self) -> Result<Vec<([u8; 33], AccountState)>, StorageError> {
+        Ok(self.accounts.iter().map(|(k, v)| (*k, *v)).collect())
+    }
+
+    fn get_undo(&self, hash: &[u8; 32]) -> Result<Option<BlockUndo>, StorageError> {
+        Ok(self.undo.get(hash).cloned())
+    }
+
+    fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.meta.get(key).cloned())
+    }
+
+    fn get_block_height(&self, hash: &[u8; 32]) -> Result<Option<u64>, StorageError> {
+        Ok(self.block_heights.get(hash).copied())
+    }
+
+    fn get_tx_location(&self, txid: &[u8; 32]) -> Result<Option<TxLocation>, StorageError> {
+        Ok(self.tx_locations.get(txid).copied())
+    }
+
+    fn address_txs(&self, account: &[u8; 33]) -> Result<Vec<([u8; 32], TxLocation)>, StorageError> {
+        Ok(self
+            .address_txs
+            .get(account)
+            .map(|txs| txs.values().copied().collect())
+            .unwrap_or_default())
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StorageError> {
+        for op in batch.ops {
+            match op {
+                WriteOp::PutBlock(block) => {
+                    let hash = block.hash();
+                    self.headers.insert(hash, block.header());
+                    self.blocks.insert(hash, block);
+                }
+                WriteOp::DeleteBlock(hash) => {
+                    self.blocks.remove(&hash);
+                }
+                WriteOp::PutHeader(header) => {
+                    self.headers.insert(header.hash(), header);
+                }
+                WriteOp::PutAccount(account, state) => {
+                    self.accounts.insert(account, state);
+                }
+                WriteOp::DeleteAccount(account) => {
+                    self.accounts.remove(&account);
+                }
+                WriteOp::PutUndo(hash, undo) => {
+                    self.undo.insert(hash, undo);
+                }
+                WriteOp::DeleteUndo(hash) => {
+                    self.undo.remove(&hash);
+                }
+                WriteOp::PutMeta(key, value) => {
+                    self.meta.insert(key, value);
+                }
+                WriteOp::PutBlockHeight(hash, height) => {
+                    self.block_heights.insert(hash, height);
+                }
+                WriteOp::DeleteBlockHeight(hash) => {
+                    self.block_heights.remove(&hash);
+                }
+                WriteOp::PutTxLocation(txid, location) => {
+                    self.tx_locations.insert(txid, location);
+                }
+                WriteOp::DeleteTxLocation(txid) => {
+                    self.tx_locations.remove(&txid);
+                }
+                WriteOp::PutAddressTx(account, txid, location) => {
+                    self.address_txs
+                        .entry(account)
+                        .or_default()
+                        .insert((location.height, location.position), (txid, location));
+                }
+                WriteOp::DeleteAddressTx(account, location) => {
+                    if let Some(txs) = self.address_txs.get_mut(&account) {
+                        txs.remove(&(location.height, location.position));
+                        if txs.is_empty() {
+                            self.address_txs.remove(&account);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
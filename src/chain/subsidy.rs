@@ -0,0 +1,38 @@
+// Smallest units per coin
+pub const COIN: u64 = 100_000_000;
+
+// Block reward paid to miners, halving every `halving_interval` blocks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SubsidySchedule {
+    pub initial: u64,
+    pub halving_interval: u64,
+}
+
+impl SubsidySchedule {
+    pub fn new(initial: u64, halving_interval: u64) -> Self {
+        Self {
+            initial,
+            halving_interval,
+        }
+    }
+
+    // Subsidy a block at `height` may claim
+    pub fn subsidy_at(&self, height: u64) -> u64 {
+        if self.halving_interval == 0 {
+            return self.initial;
+        }
+
+        let halvings = height / self.halving_interval;
+        if halvings >= u64::BITS as u64 {
+            return 0;
+        }
+
+        self.initial >> halvings
+    }
+}
+
+impl Default for SubsidySchedule {
+    fn default() -> Self {
+        Self::new(50 * COIN, 210_000)
+    }
+}
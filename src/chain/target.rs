@@ -0,0 +1,269 @@
+use std::fmt;
+use std::ops::{Div, Not, Shl, Shr};
+
+// Unsigned 256-bit integer used for proof-of-work targets. Limbs are stored
+// most significant first so the derived ordering is numeric ordering.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const ONE: U256 = U256([0, 0, 0, 1]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub fn from_u64(value: u64) -> Self {
+        U256([0, 0, 0, value])
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0; 4];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+            *limb = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+        U256(limbs)
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (chunk, limb) in bytes.chunks_exact_mut(8).zip(self.0) {
+            chunk.copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == U256::ZERO
+    }
+
+    pub fn low_u64(&self) -> u64 {
+        self.0[3]
+    }
+
+    // Number of bits needed to represent the value
+    pub fn bits(&self) -> u32 {
+        for (i, limb) in self.0.iter().enumerate() {
+            if *limb != 0 {
+                return (4 - i as u32) * 64 - limb.leading_zeros();
+            }
+        }
+        0
+    }
+
+    pub fn overflowing_add(self, rhs: U256) -> (U256, bool) {
+        let mut limbs = [0; 4];
+        let mut carry = false;
+        for i in (0..4).rev() {
+            let (sum, c1) = self.0[i].overflowing_add(rhs.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            limbs[i] = sum;
+            carry = c1 || c2;
+        }
+        (U256(limbs), carry)
+    }
+
+    pub fn overflowing_sub(self, rhs: U256) -> (U256, bool) {
+        let mut limbs = [0; 4];
+        let mut borrow = false;
+        for i in (0..4).rev() {
+            let (diff, b1) = self.0[i].overflowing_sub(rhs.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            limbs[i] = diff;
+            borrow = b1 || b2;
+        }
+        (U256(limbs), borrow)
+    }
+
+    pub fn checked_add(self, rhs: U256) -> Option<U256> {
+        match self.overflowing_add(rhs) {
+            (sum, false) => Some(sum),
+            _ => None,
+        }
+    }
+
+    pub fn saturating_add(self, rhs: U256) -> U256 {
+        self.checked_add(rhs).unwrap_or(U256::MAX)
+    }
+
+    pub fn checked_sub(self, rhs: U256) -> Option<U256> {
+        match self.overflowing_sub(rhs) {
+            (diff, false) => Some(diff),
+            _ => None,
+        }
+    }
+
+    pub fn checked_mul_u64(self, rhs: u64) -> Option<U256> {
+        let mut limbs = [0; 4];
+        let mut carry = 0u128;
+        for i in (0..4).rev() {
+            let product = self.0[i] as u128 * rhs as u128 + carry;
+            limbs[i] = product as u64;
+            carry = product >> 64;
+        }
+        if carry == 0 { Some(U256(limbs)) } else { None }
+    }
+
+    pub fn div_u64(self, rhs: u64) -> U256 {
+        let mut limbs = [0; 4];
+        let mut remainder = 0u128;
+        for (limb, value) in limbs.iter_mut().zip(self.0) {
+            let dividend = (remainder << 64) | value as u128;
+            *limb = (dividend / rhs as u128) as u64;
+            remainder = dividend % rhs as u128;
+        }
+        U256(limbs)
+    }
+
+    // Decode the compact "bits" representation used in block headers: a
+    // one byte length followed by a three byte mantissa. Returns None for
+    // negative or overflowing encodings.
+    pub fn from_compact(bits: u32) -> Option<U256> {
+        let size = bits >> 24;
+        let mantissa = bits & 0x007f_ffff;
+
+        if mantissa == 0 {
+            return Some(U256::ZERO);
+        }
+        if bits & 0x0080_0000 != 0 {
+            return None;
+        }
+
+        if size <= 3 {
+            return Some(U256::from_u64((mantissa >> (8 * (3 - size))) as u64));
+        }
+
+        let value = U256::from_u64(mantissa as u64) << (8 * (size - 3));
+        if value >> (8 * (size - 3)) != U256::from_u64(mantissa as u64) {
+            return None;
+        }
+        Some(value)
+    }
+
+    // Encode to the compact representation, dropping precision below the
+    // three most significant bytes
+    pub fn to_compact(&self) -> u32 {
+        let mut size = self.bits().div_ceil(8);
+        let mut mantissa = if size <= 3 {
+            (self.low_u64() << (8 * (3 - size))) as u32
+        } else {
+            (*self >> (8 * (size - 3))).low_u64() as u32
+        };
+
+        // The top mantissa bit is the sign, so shift it into the next byte
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+
+        mantissa | (size << 24)
+    }
+}
+
+// Expected number of hashes needed to meet the target encoded in `bits`,
+// 2^256 / (target + 1). Used to compare the total work behind competing chains.
+pub fn block_work(bits: u32) -> U256 {
+    match U256::from_compact(bits) {
+        // Written as !target / (target + 1) + 1 since 2^256 doesn't fit
+        Some(target) if target != U256::MAX => (!target / target.checked_add(U256::ONE).unwrap())
+            .checked_add(U256::ONE)
+            .unwrap(),
+        _ => U256::ZERO,
+    }
+}
+
+// Whether a hash, read as a big-endian number, is at or below the target
+// encoded in `bits`
+pub fn hash_meets_target(hash: &[u8; 32], bits: u32) -> bool {
+    match U256::from_compact(bits) {
+        Some(target) => U256::from_be_bytes(*hash) <= target,
+        None => false,
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: u32) -> U256 {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+
+        let (limb_shift, bit_shift) = ((shift / 64) as usize, shift % 64);
+        let limb = |i: usize| self.0.get(i).copied().unwrap_or(0);
+
+        U256(std::array::from_fn(|i| {
+            let high = limb(i + limb_shift) << bit_shift;
+            match bit_shift {
+                0 => high,
+                _ => high | limb(i + limb_shift + 1) >> (64 - bit_shift),
+            }
+        }))
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: u32) -> U256 {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+
+        let (limb_shift, bit_shift) = ((shift / 64) as usize, shift % 64);
+        let limb = |i: Option<usize>| i.map_or(0, |i| self.0[i]);
+
+        U256(std::array::from_fn(|i| {
+            let low = limb(i.checked_sub(limb_shift)) >> bit_shift;
+            match bit_shift {
+                0 => low,
+                _ => low | limb(i.checked_sub(limb_shift + 1)) << (64 - bit_shift),
+            }
+        }))
+    }
+}
+
+impl Not for U256 {
+    type Output = U256;
+
+    fn not(self) -> U256 {
+        U256(self.0.map(|limb| !limb))
+    }
+}
+
+// Binary long division, panicking on a zero divisor like the primitive types
+impl Div for U256 {
+    type Output = U256;
+
+    fn div(self, rhs: U256) -> U256 {
+        assert!(!rhs.is_zero(), "attempt to divide by zero");
+
+        if self < rhs {
+            return U256::ZERO;
+        }
+
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for bit in (0..self.bits()).rev() {
+            remainder = remainder << 1;
+            if (self >> bit).low_u64() & 1 == 1 {
+                remainder.0[3] |= 1;
+            }
+            if remainder >= rhs {
+                remainder = remainder.checked_sub(rhs).unwrap();
+                quotient = quotient.checked_add(U256::ONE << bit).unwrap();
+            }
+        }
+        quotient
+    }
+}
+
+impl fmt::Debug for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "U256({})", hex::encode(self.to_be_bytes()))
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.to_be_bytes()))
+    }
+}
@@ -1,27 +1,217 @@
+use secp256k1::{Message, PublicKey, SecretKey, ecdsa::Signature};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fmt;
 
-#[derive(Clone)]
+use crate::chain::account::Account;
+use crate::chain::encoding::{self, EncodingError, byte_array, option_byte_array};
+use crate::chain::merkle::MerkleProof;
+
+// Coinbase transactions mint new coins, so they have no real sender
+pub const COINBASE_SENDER: [u8; 33] = [0; 33];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Transaction {
+    #[serde(with = "byte_array")]
     pub recipient: [u8; 33],
+    #[serde(with = "byte_array")]
     pub sender: [u8; 33],
     pub amount: u64,
+    pub fee: u64,
+    pub nonce: u64,
+    #[serde(with = "option_byte_array")]
+    pub signature: Option<[u8; 64]>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TransactionError {
+    ZeroAmount,
+    SelfTransfer,
+    WrongSigner,
+    InvalidPrivateKey,
+    InvalidPublicKey,
+    MissingSignature,
+    InvalidSignature,
+    AlreadyInMempool,
+    InsufficientFunds { available: u64, required: u64 },
+    BalanceOverflow,
+    NonceTooLow { expected: u64, found: u64 },
+    NonceTooHigh { expected: u64, found: u64 },
+    NonceAlreadyPending,
+    UnexpectedCoinbase,
+    MempoolFull,
+    Storage(String),
 }
 
 impl Transaction {
-    pub fn new(recipient: [u8; 33], sender: [u8; 33], amount: u64) -> Self {
+    pub fn new(recipient: [u8; 33], sender: [u8; 33], amount: u64, fee: u64, nonce: u64) -> Self {
         Self {
             recipient,
             sender,
             amount,
+            fee,
+            nonce,
+            signature: None,
         }
     }
+
+    // Pay the block reward to a miner. The nonce holds the block height so
+    // every coinbase has a distinct txid.
+    pub fn coinbase(recipient: [u8; 33], amount: u64, height: u64) -> Self {
+        Self::new(recipient, COINBASE_SENDER, amount, 0, height)
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.sender == COINBASE_SENDER
+    }
+
+    // Amount plus fee, debited from the sender
+    pub fn total_cost(&self) -> Result<u64, TransactionError> {
+        self.amount
+            .checked_add(self.fee)
+            .ok_or(TransactionError::BalanceOverflow)
+    }
+
+    // The bytes covered by the signature: every field except the signature itself
+    pub fn signing_preimage(&self) -> Vec<u8> {
+        let mut preimage = Vec::with_capacity(33 + 33 + 8 + 8 + 8);
+        preimage.extend_from_slice(&self.recipient);
+        preimage.extend_from_slice(&self.sender);
+        preimage.extend_from_slice(&self.amount.to_le_bytes());
+        preimage.extend_from_slice(&self.fee.to_le_bytes());
+        preimage.extend_from_slice(&self.nonce.to_le_bytes());
+        preimage
+    }
+
+    fn signing_message(&self) -> Message {
+        Message::from_digest(Sha256::digest(self.signing_preimage()).into())
+    }
+
+    // Sign the transaction with the sender's private key
+    pub fn sign(&mut self, account: &Account) -> Result<(), TransactionError> {
+        if account.public_key != self.sender {
+            return Err(TransactionError::WrongSigner);
+        }
+
+        let secret_key = SecretKey::from_byte_array(&account.private_key)
+            .map_err(|_| TransactionError::InvalidPrivateKey)?;
+        let signature = secret_key.sign_ecdsa(self.signing_message());
+
+        self.signature = Some(signature.serialize_compact());
+        Ok(())
+    }
+
+    // Check the signature against the compressed sender key
+    pub fn verify(&self) -> Result<(), TransactionError> {
+        let signature = self.signature.ok_or(TransactionError::MissingSignature)?;
+
+        let public_key = PublicKey::from_byte_array_compressed(&self.sender)
+            .map_err(|_| TransactionError::InvalidPublicKey)?;
+        let signature =
+            Signature::from_compact(&signature).map_err(|_| TransactionError::InvalidSignature)?;
+
+        secp256k1::SECP256K1
+            .verify_ecdsa(&self.signing_message(), &signature, &public_key)
+            .map_err(|_| TransactionError::InvalidSignature)
+    }
+
+    // Canonical versioned encoding, including the signature
+    pub fn encode(&self) -> Vec<u8> {
+        encoding::encode(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, EncodingError> {
+        encoding::decode(bytes)
+    }
+
+    // Double SHA-256 of the canonical encoding
+    pub fn txid(&self) -> [u8; 32] {
+        Sha256::digest(Sha256::digest(self.encode())).into()
+    }
+
+    // Check that this transaction is included under a block's merkle root
+    pub fn verify_inclusion(&self, proof: &MerkleProof, merkle_root: [u8; 32]) -> bool {
+        proof.verify(self.txid(), merkle_root)
+    }
+
+    // Stateless checks that don't depend on the ledger
+    pub fn validate(&self) -> Result<(), TransactionError> {
+        if self.is_coinbase() {
+            return match self.signature {
+                Some(_) => Err(TransactionError::InvalidSignature),
+                None => Ok(()),
+            };
+        }
+
+        if self.amount == 0 {
+            return Err(TransactionError::ZeroAmount);
+        }
+
+        if self.sender == self.recipient {
+            return Err(TransactionError::SelfTransfer);
+        }
+
+        self.verify()
+    }
 }
 
 impl fmt::Display for Transaction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "Transaction")?;
+        writeln!(f, "Transaction {}", hex::encode(self.txid()))?;
         writeln!(f, "  Recipient: {}", hex::encode(self.recipient))?;
         writeln!(f, "  Sender:    {}", hex::encode(self.sender))?;
-        writeln!(f, "  Amount:    {}", self.amount)
+        writeln!(f, "  Amount:    {}", self.amount)?;
+        writeln!(f, "  Fee:       {}", self.fee)?;
+        writeln!(f, "  Nonce:     {}", self.nonce)
     }
 }
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::ZeroAmount => write!(f, "amount must be greater than zero"),
+            TransactionError::SelfTransfer => write!(f, "sender and recipient are the same"),
+            TransactionError::WrongSigner => write!(f, "signing account is not the sender"),
+            TransactionError::InvalidPrivateKey => write!(f, "private key is invalid"),
+            TransactionError::InvalidPublicKey => write!(f, "sender is not a valid public key"),
+            TransactionError::MissingSignature => write!(f, "transaction is not signed"),
+            TransactionError::InvalidSignature => write!(f, "signature does not match the sender"),
+            TransactionError::AlreadyInMempool => {
+                write!(f, "transaction is already in the mempool")
+            }
+            TransactionError::InsufficientFunds {
+                available,
+                required,
+            } => write!(
+                f,
+                "insufficient funds: {} available, {} required",
+                available, required
+            ),
+            TransactionError::BalanceOverflow => write!(f, "balance would overflow"),
+            TransactionError::NonceTooLow { expected, found } => {
+                write!(f, "nonce {} already used, next is {}", found, expected)
+            }
+            TransactionError::NonceTooHigh { expected, found } => {
+                write!(f, "nonce {} is too far ahead of {}", found, expected)
+            }
+            TransactionError::NonceAlreadyPending => {
+                write!(f, "a transaction with this nonce is already pending")
+            }
+            TransactionError::UnexpectedCoinbase => {
+                write!(
+                    f,
+                    "coinbase transactions are only valid at the start of a block"
+                )
+            }
+            TransactionError::Storage(e) => write!(f, "could not read chain state: {}", e),
+            TransactionError::MempoolFull => {
+                write!(
+                    f,
+                    "mempool is full of transactions paying a higher fee rate"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
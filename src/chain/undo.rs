@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::chain::encoding::byte_array;
+use crate::chain::state::AccountState;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UndoEntry {
+    #[serde(with = "byte_array")]
+    pub account: [u8; 33],
+    // None if the account had no entry before the block
+    pub previous: Option<AccountState>,
+}
+
+// What a block overwrote when it was connected, so its effects on the
+// ledger can be reverted without replaying the chain
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BlockUndo {
+    pub entries: Vec<UndoEntry>,
+}
+
+impl BlockUndo {
+    // Record the current state of every account the update is about to change
+    pub fn capture(
+        update: &HashMap<[u8; 33], AccountState>,
+        previous: impl Fn(&[u8; 33]) -> Option<AccountState>,
+    ) -> Self {
+        let mut entries: Vec<UndoEntry> = update
+            .keys()
+            .map(|account| UndoEntry {
+                account: *account,
+                previous: previous(account),
+            })
+            .collect();
+
+        // Keep the encoding independent of hash map order
+        entries.sort_unstable_by_key(|entry| entry.account);
+        Self { entries }
+    }
+
+    // Put the accounts back the way they were before the block
+    pub fn revert(self, accounts: &mut HashMap<[u8; 33], AccountState>) {
+        for entry in self.entries {
+            match entry.previous {
+                Some(state) => accounts.insert(entry.account, state),
+                None => accounts.remove(&entry.account),
+            };
+        }
+    }
+}
@@ -1,57 +1,138 @@
-mod chain;
-mod message;
-mod network;
-
-use crate::chain::account::Account;
-use crate::chain::blockchain::Blockchain;
-use crate::chain::transaction::Transaction;
-use crate::network::client::Client;
-use crate::network::server::Server;
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::Blockchain;
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::storage::StorageError;
+use rust_blockchain::chain::transaction::Transaction;
+use rust_blockchain::network::client::Client;
+use rust_blockchain::network::server::Server;
+
+// Print the tip block, or just its hash for a chain bootstrapped from a
+// snapshot that has no body for it yet
+#[allow(dead_code)]
+fn print_tip(blockchain: &Blockchain) -> Result<(), StorageError> {
+    match blockchain.tip()? {
+        Some(block) => print!("{}", block),
+        None => println!("Tip {} (header only)", hex::encode(blockchain.tip_hash())),
+    }
+    Ok(())
+}
 
-fn chain_example() -> () {
+#[allow(dead_code)]
+fn chain_example() -> Result<(), Box<dyn std::error::Error>> {
     // let block = Block::new(0, [0; 32], [0; 32], 0);
 
     // println!("{:?}", block); // Debug print
     // println!("{:#?}", block); // Pretty-printed Debug
     // println!("{}", hex::encode(0x00));
 
-    let mut blockchain = Blockchain::new(4);
+    let mut blockchain = Blockchain::open(ChainParams::mainnet(), "data")?;
     let account1 = Account::new(String::from("aj"));
     let account2 = Account::new(String::from("justin"));
 
-    let tx1 = Transaction::new(
-        account1.public_key.clone(),
-        account2.public_key.clone(),
-        100,
-    );
+    let mut tx1 = Transaction::new(account1.public_key, account2.public_key, 100, 1, 0);
+    tx1.sign(&account2).expect("account2 is the sender");
 
     print!("{}", account1);
     print!("{}", account2);
-    println!("");
+    println!();
+
+    print_tip(&blockchain)?;
 
-    print!("{}", blockchain.tip());
+    println!("Mining...");
+    if let Err(e) = blockchain.mine_block(account2.public_key) {
+        eprintln!("Mined block rejected: {}", e);
+    }
+    print_tip(&blockchain)?;
 
-    blockchain.add_transaction(tx1);
+    if let Err(e) = blockchain.add_transaction(tx1) {
+        eprintln!("Transaction rejected: {}", e);
+    }
 
     println!("Mining...");
-    blockchain.mine_block();
-    print!("{}", blockchain.tip());
+    if let Err(e) = blockchain.mine_block(account2.public_key) {
+        eprintln!("Mined block rejected: {}", e);
+    }
+    print_tip(&blockchain)?;
 
-    println!("AJ Balance: {}", blockchain.balances[&account1.public_key]);
+    println!("AJ Balance: {}", blockchain.balance(&account1.public_key)?);
 
     println!("Mining...");
-    blockchain.mine_block();
-    print!("{}", blockchain.tip());
+    if let Err(e) = blockchain.mine_block(account1.public_key) {
+        eprintln!("Mined block rejected: {}", e);
+    }
+    print_tip(&blockchain)?;
+
+    Ok(())
+}
+
+// Load a chain from a data directory, or a file exported with
+// `Blockchain::encode_blocks`, and check it from genesis, reporting the first
+// invalid block
+fn verify(path: &str, network: &str) -> std::io::Result<()> {
+    let params = match ChainParams::from_name(network) {
+        Some(params) => params,
+        None => {
+            eprintln!("Unknown network: {}", network);
+            std::process::exit(1);
+        }
+    };
+
+    let blockchain = if std::path::Path::new(path).is_dir() {
+        match Blockchain::open(params, path) {
+            Ok(blockchain) => blockchain,
+            Err(e) => {
+                eprintln!("Could not open {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        let bytes = std::fs::read(path)?;
+
+        let blocks = match Blockchain::decode_blocks(&bytes) {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                eprintln!("Could not decode {}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+
+        match Blockchain::from_blocks(params, blocks) {
+            Ok(blockchain) => blockchain,
+            Err(e) => {
+                eprintln!("Chain is invalid: {}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    match blockchain.validate_chain() {
+        Ok(()) => {
+            println!(
+                "Chain is valid: {} blocks, tip {}",
+                blockchain.chain.len(),
+                hex::encode(blockchain.tip_hash())
+            );
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("Chain is invalid: {}", e);
+            std::process::exit(1);
+        }
+    }
 }
 
 fn main() -> std::io::Result<()> {
     // chain_example();
     let args: Vec<String> = std::env::args().collect();
 
-    if args.len() != 3 {
+    if args.len() != 3 && !(args.len() == 4 && args[1] == "verify") {
         eprintln!("Usage:");
         eprintln!("  {} server <addr:port>", args[0]);
         eprintln!("  {} client <addr:port>", args[0]);
+        eprintln!(
+            "  {} verify <data dir or chain file> [mainnet|testnet|regtest]",
+            args[0]
+        );
         return Ok(());
     }
 
@@ -68,9 +149,10 @@ fn main() -> std::io::Result<()> {
             loop {
                 let mut input = String::new();
                 stdin.read_line(&mut input)?;
-                client.send(&input);
+                client.send(&input)?;
             }
         }
+        "verify" => verify(&args[2], args.get(3).map_or("mainnet", |s| s.as_str()))?,
         _ => println!("Unknown mode"),
     }
 
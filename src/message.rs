@@ -1,13 +1,14 @@
-use bincode;
 use serde::{Deserialize, Serialize};
 
+use crate::chain::block::Block;
+use crate::chain::transaction::Transaction;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Message {
     Ping,
     Pong,
-    Transaction {
-        from: String,
-        to: String,
-        amount: u64,
-    },
+    Transaction(Transaction),
+    Block(Block),
+    GetTransaction { txid: [u8; 32] },
+    GetBlock { hash: [u8; 32] },
 }
@@ -1,13 +1,8 @@
-use bincode;
-use serde::{Deserialize, Serialize};
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::thread;
-
-use crate::message::Message;
+use std::io::Write;
+use std::net::TcpStream;
 
 pub struct Client {
-    addr: String,
+    pub addr: String,
     stream: TcpStream,
 }
 
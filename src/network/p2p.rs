@@ -1,8 +1,3 @@
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::{Arc, Mutex};
-use std::thread;
-
 // struct P2PInterface {
 //     peers: Arc<Mutex<HashMap<SocketAddr, Arc<Mutex<TcpStream>>>>>,
 // }
@@ -1,4 +1,4 @@
-use std::io::{Read, Write};
+use std::io::Read;
 use std::net::{TcpListener, TcpStream};
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -42,7 +42,6 @@ impl Server {
                 Err(e) => {
                     eprintln!("Connection failed: {}", e);
                 }
-                _ => eprintln!("error"),
             }
         }
 
@@ -51,7 +50,7 @@ impl Server {
 
     fn handle_client(
         mut stream: TcpStream,
-        peers: Arc<Mutex<Vec<TcpStream>>>,
+        _peers: Arc<Mutex<Vec<TcpStream>>>,
     ) -> std::io::Result<()> {
         let mut buf = [0; 1024];
 
@@ -0,0 +1,98 @@
+mod common;
+
+use common::{child, solve, transfer};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::Block;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::transaction::TransactionError;
+
+#[test]
+fn valid_block_extends_the_chain() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let miner = Account::new("miner".to_string());
+    let mut params = ChainParams::regtest();
+    params.premine = vec![(alice.public_key, 10)];
+    let mut blockchain = Blockchain::new(params);
+
+    let tx = transfer(&alice, &bob, 5, 0);
+    let block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        vec![tx],
+    );
+    let hash = block.hash();
+    blockchain.add_block(block).unwrap();
+    assert_eq!(blockchain.chain.len(), 2);
+    assert_eq!(blockchain.tip().unwrap().unwrap().hash(), hash);
+    assert_eq!(blockchain.balance(&bob.public_key).unwrap(), 5);
+    assert_eq!(blockchain.balance(&alice.public_key).unwrap(), 5);
+
+    blockchain.mine_block(miner.public_key).unwrap();
+    assert_eq!(blockchain.chain.len(), 3);
+    assert_eq!(blockchain.tip().unwrap().unwrap().prev_hash, hash);
+}
+
+#[test]
+fn invalid_blocks_leave_the_chain_unchanged() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let miner = Account::new("miner".to_string());
+    let genesis = blockchain.tip().unwrap().unwrap().hash();
+
+    let block = solve(Block::new(2, genesis, blockchain.bits(), vec![]));
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::InvalidIndex {
+            expected: 1,
+            found: 2
+        })
+    );
+
+    let block = solve(Block::new(1, [7; 32], blockchain.bits(), vec![]));
+    assert_eq!(blockchain.add_block(block), Err(BlockError::UnknownParent));
+
+    let mut block = Block::new(1, genesis, blockchain.bits(), vec![]);
+    block.timestamp = blockchain.tip().unwrap().unwrap().timestamp + 1;
+    while block.meets_target() {
+        block.nonce += 1;
+    }
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::InsufficientWork)
+    );
+
+    let tx = transfer(&alice, &bob, 5, 0);
+    let mut block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        vec![tx],
+    );
+    block.data[1].amount = 6;
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::InvalidMerkleRoot)
+    );
+
+    let tx = transfer(&alice, &bob, 0, 0);
+    let block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        vec![tx],
+    );
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::InvalidTransaction(
+            1,
+            TransactionError::ZeroAmount
+        ))
+    );
+
+    assert_eq!(blockchain.chain.len(), 1);
+    assert!(blockchain.store.accounts().unwrap().is_empty());
+}
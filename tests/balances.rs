@@ -0,0 +1,110 @@
+mod common;
+
+use common::{child, transfer};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::transaction::TransactionError;
+
+fn funded(account: &Account, balance: u64) -> Blockchain {
+    let mut params = ChainParams::regtest();
+    params.premine = vec![(account.public_key, balance)];
+    Blockchain::new(params)
+}
+
+#[test]
+fn mempool_refuses_overdrafts() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let mut blockchain = funded(&alice, 10);
+
+    assert_eq!(
+        blockchain.add_transaction(transfer(&bob, &alice, 1, 0)),
+        Err(TransactionError::InsufficientFunds {
+            available: 0,
+            required: 1
+        })
+    );
+
+    blockchain
+        .add_transaction(transfer(&alice, &bob, 7, 0))
+        .unwrap();
+    // Pending spends count against the balance
+    assert_eq!(
+        blockchain.add_transaction(transfer(&alice, &bob, 4, 1)),
+        Err(TransactionError::InsufficientFunds {
+            available: 10,
+            required: 11
+        })
+    );
+    blockchain
+        .add_transaction(transfer(&alice, &bob, 3, 1))
+        .unwrap();
+    assert_eq!(blockchain.mempool.len(), 2);
+    assert_eq!(blockchain.pending_spend(&alice.public_key), Ok(10));
+
+    let miner = Account::new("miner".to_string());
+    blockchain.mine_block(miner.public_key).unwrap();
+    assert_eq!(blockchain.balance(&alice.public_key).unwrap(), 0);
+    assert_eq!(blockchain.balance(&bob.public_key).unwrap(), 10);
+    assert!(blockchain.mempool.is_empty());
+}
+
+#[test]
+fn stateless_rules_reject_pointless_transfers() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let mut blockchain = funded(&alice, 10);
+
+    assert_eq!(
+        blockchain.add_transaction(transfer(&alice, &bob, 0, 0)),
+        Err(TransactionError::ZeroAmount)
+    );
+    assert_eq!(
+        blockchain.add_transaction(transfer(&alice, &alice, 1, 0)),
+        Err(TransactionError::SelfTransfer)
+    );
+}
+
+#[test]
+fn blocks_spending_more_than_the_balance_are_rejected() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let carol = Account::new("carol".to_string());
+    let miner = Account::new("miner".to_string());
+    let mut blockchain = funded(&alice, 10);
+
+    let overdraft = vec![transfer(&alice, &bob, 6, 0), transfer(&alice, &bob, 5, 1)];
+    let block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        overdraft,
+    );
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::InvalidTransaction(
+            2,
+            TransactionError::InsufficientFunds {
+                available: 4,
+                required: 5
+            }
+        ))
+    );
+    assert_eq!(blockchain.chain.len(), 1);
+    assert_eq!(blockchain.balance(&alice.public_key).unwrap(), 10);
+    assert_eq!(blockchain.balance(&bob.public_key).unwrap(), 0);
+
+    // Money received earlier in the same block can be spent
+    let payments = vec![transfer(&alice, &bob, 10, 0), transfer(&bob, &carol, 4, 0)];
+    let block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        payments,
+    );
+    blockchain.add_block(block).unwrap();
+    assert_eq!(blockchain.balance(&alice.public_key).unwrap(), 0);
+    assert_eq!(blockchain.balance(&bob.public_key).unwrap(), 6);
+    assert_eq!(blockchain.balance(&carol.public_key).unwrap(), 4);
+}
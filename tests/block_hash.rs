@@ -0,0 +1,84 @@
+mod common;
+
+use common::{child, transfer};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::Block;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::params::ChainParams;
+
+#[test]
+fn hash_changes_with_the_transactions() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let pay = |amount| transfer(&alice, &bob, amount, 0);
+
+    let block = Block::new(1, [0; 32], 0x207fffff, vec![pay(5)]);
+    let mut other = Block::new(1, [0; 32], 0x207fffff, vec![pay(5)]);
+    other.timestamp = block.timestamp;
+    assert_eq!(other.hash(), block.hash());
+
+    other.data = vec![pay(6)];
+    other.merkle_root = Block::calculate_merkle_root(&other.data);
+    assert_ne!(other.hash(), block.hash());
+
+    let mut reordered = Block::new(1, [0; 32], 0x207fffff, vec![pay(1), pay(2)]);
+    let hash = reordered.hash();
+    reordered.data.reverse();
+    reordered.merkle_root = Block::calculate_merkle_root(&reordered.data);
+    assert_ne!(reordered.hash(), hash);
+}
+
+#[test]
+fn swapped_body_is_rejected() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let miner = Account::new("miner".to_string());
+    let mut params = ChainParams::regtest();
+    params.premine = vec![(alice.public_key, 5)];
+    let mut blockchain = Blockchain::new(params);
+
+    // Same proof of work, different payment
+    let tx = transfer(&alice, &bob, 5, 0);
+    let mut forged = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        vec![tx.clone()],
+    );
+    let hash = forged.hash();
+    forged.data[1].amount = 500;
+    assert_eq!(forged.hash(), hash);
+    assert_eq!(
+        blockchain.add_block(forged),
+        Err(BlockError::InvalidMerkleRoot)
+    );
+
+    let block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        vec![tx],
+    );
+    blockchain.add_block(block).unwrap();
+    assert_eq!(blockchain.balance(&bob.public_key).unwrap(), 5);
+}
+
+#[test]
+fn duplicated_transactions_are_rejected() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let txs: Vec<_> = (1..=3)
+        .map(|amount| transfer(&alice, &bob, amount, 0))
+        .collect();
+    let block = Block::new(1, [0; 32], 0x207fffff, txs.clone());
+    assert!(block.verify_merkle_root());
+
+    // Repeating the odd last transaction keeps the same root
+    let mut mutated = Block::new(1, [0; 32], 0x207fffff, txs.clone());
+    mutated.data.push(txs[2].clone());
+    assert_eq!(
+        Block::calculate_merkle_root(&mutated.data),
+        block.merkle_root
+    );
+    assert!(!mutated.verify_merkle_root());
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use common::{child, extend, temp_dir, transfer, unlocked};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::Blockchain;
+use rust_blockchain::chain::disk_store::DiskStore;
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::storage::StorageError;
+
+// Open the chain in `dir` with segments small enough to roll over quickly
+fn open(params: &ChainParams, dir: &std::path::Path) -> Blockchain {
+    let mut store = DiskStore::open(dir, params.magic).unwrap();
+    store.blocks.segment_size = 600;
+    Blockchain::with_store(params.clone(), Box::new(store)).unwrap()
+}
+
+#[test]
+fn blocks_survive_a_restart() {
+    let dir = temp_dir("block-storage");
+    let params = ChainParams::regtest();
+    let miner = Account::new("miner".to_string());
+    let alice = Account::new("alice".to_string());
+
+    let tip;
+    {
+        let mut blockchain = open(&params, &dir);
+        let genesis = blockchain.chain[0];
+
+        let parent = extend(&mut blockchain, genesis, &miner, 2, 10)[1];
+        let block = child(
+            &blockchain,
+            &blockchain.get_block(&parent).unwrap().unwrap(),
+            &miner,
+            vec![transfer(&miner, &alice, 5, 0)],
+        );
+        let parent = block.hash();
+        blockchain.add_block(block).unwrap();
+        extend(&mut blockchain, parent, &miner, 2, 13);
+
+        // Side branches are stored too
+        let fork = blockchain.chain[3];
+        extend(&mut blockchain, fork, &alice, 1, 40);
+        tip = *blockchain.tip_hash();
+        assert_eq!(blockchain.store.headers().unwrap().len(), 7);
+    }
+
+    assert!(fs::read_dir(dir.join("blocks")).unwrap().count() > 1);
+    let blockchain = unlocked(|| Blockchain::open(params.clone(), &dir)).unwrap();
+    assert_eq!(*blockchain.tip_hash(), tip);
+    assert_eq!(blockchain.height(), 5);
+    assert_eq!(blockchain.tree.len(), 7);
+    assert_eq!(blockchain.balance(&alice.public_key).unwrap(), 5);
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+    drop(blockchain);
+
+    let store = unlocked(|| DiskStore::open(&dir, params.magic)).unwrap();
+    assert_eq!(store.blocks.len(), 7);
+    assert_eq!(store.blocks.at_height(4).unwrap().len(), 2);
+    drop(store);
+
+    // A different genesis on the same network doesn't match what's stored
+    let mut other = params;
+    other.premine = vec![(alice.public_key, 1)];
+    assert!(matches!(
+        unlocked(|| Blockchain::open(other.clone(), &dir)),
+        Err(StorageError::GenesisMismatch)
+    ));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn torn_append_is_cut_off_and_corruption_reported() {
+    let dir = temp_dir("block-storage-torn");
+    let params = ChainParams::regtest();
+    let miner = Account::new("miner".to_string());
+
+    let tip;
+    {
+        let mut blockchain = open(&params, &dir);
+        let genesis = blockchain.chain[0];
+        tip = *extend(&mut blockchain, genesis, &miner, 4, 10)
+            .last()
+            .unwrap();
+    }
+
+    // A crash part way through an append leaves a partial record behind
+    let store = unlocked(|| DiskStore::open(&dir, params.magic)).unwrap();
+    let last = store.blocks.location(&tip).unwrap().unwrap();
+    drop(store);
+    let path = dir
+        .join("blocks")
+        .join(format!("blk{:05}.dat", last.segment));
+    let len = fs::metadata(&path).unwrap().len();
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&params.magic).unwrap();
+    file.write_all(&[200, 0, 0, 0, 1, 2]).unwrap();
+    drop(file);
+
+    let blockchain = unlocked(|| Blockchain::open(params.clone(), &dir)).unwrap();
+    assert_eq!(*blockchain.tip_hash(), tip);
+    assert!(blockchain.get_block(&tip).unwrap().is_some());
+    assert_eq!(fs::metadata(&path).unwrap().len(), len);
+    drop(blockchain);
+
+    // Damage anywhere before the end can't be explained by a crash. Opening
+    // doesn't read the segments, so it's reported when the block is read.
+    let first = dir.join("blocks").join("blk00000.dat");
+    let mut bytes = fs::read(&first).unwrap();
+    bytes[20] ^= 0xff;
+    fs::write(&first, &bytes).unwrap();
+    let blockchain = unlocked(|| Blockchain::open(params.clone(), &dir)).unwrap();
+    assert!(matches!(
+        blockchain.get_block(&blockchain.chain[0]),
+        Err(StorageError::Corrupt {
+            segment: 0,
+            offset: 0
+        })
+    ));
+    drop(blockchain);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reorg_on_disk_is_kept_after_a_restart() {
+    let dir = temp_dir("block-storage-reorg");
+    let params = ChainParams::regtest();
+    let miner = Account::new("miner".to_string());
+    let alice = Account::new("alice".to_string());
+
+    {
+        let mut blockchain = Blockchain::open(params.clone(), &dir).unwrap();
+        let genesis = blockchain.chain[0];
+        let a1 = extend(&mut blockchain, genesis, &miner, 1, 1)[0];
+        let a2 = child(
+            &blockchain,
+            &blockchain.get_block(&a1).unwrap().unwrap(),
+            &miner,
+            vec![transfer(&miner, &alice, 3, 0)],
+        );
+        blockchain.add_block(a2).unwrap();
+        let tip = *extend(&mut blockchain, genesis, &alice, 3, 3)
+            .last()
+            .unwrap();
+        assert_eq!(*blockchain.tip_hash(), tip);
+        assert_eq!(blockchain.balance(&miner.public_key).unwrap(), 0);
+    }
+
+    let blockchain = unlocked(|| Blockchain::open(params.clone(), &dir)).unwrap();
+    assert_eq!(blockchain.height(), 3);
+    assert_eq!(blockchain.balance(&miner.public_key).unwrap(), 0);
+    assert_eq!(
+        blockchain.balance(&alice.public_key).unwrap(),
+        3 * blockchain.params.subsidy.subsidy_at(1)
+    );
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+    drop(blockchain);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::{solve, transfer};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::Block;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::subsidy::{COIN, SubsidySchedule};
+use rust_blockchain::chain::transaction::{Transaction, TransactionError};
+
+// Solved child of genesis with the given body
+fn with_body(blockchain: &Blockchain, data: Vec<Transaction>) -> Block {
+    let mut block = Block::new(
+        1,
+        blockchain.tip().unwrap().unwrap().hash(),
+        blockchain.bits(),
+        data,
+    );
+    block.timestamp = blockchain.tip().unwrap().unwrap().timestamp + 1;
+    block.state_root = blockchain
+        .state_root_after(blockchain.tip_hash(), &block.data)
+        .unwrap_or_default();
+    solve(block)
+}
+
+#[test]
+fn subsidy_halves_on_schedule() {
+    let schedule = SubsidySchedule::default();
+    assert_eq!(schedule.subsidy_at(0), 50 * COIN);
+    assert_eq!(schedule.subsidy_at(209_999), 50 * COIN);
+    assert_eq!(schedule.subsidy_at(210_000), 25 * COIN);
+    assert_eq!(schedule.subsidy_at(420_000), 25 * COIN / 2);
+    assert_eq!(schedule.subsidy_at(64 * 210_000), 0);
+    assert_eq!(SubsidySchedule::new(7, 0).subsidy_at(u64::MAX), 7);
+}
+
+#[test]
+fn miner_collects_the_subsidy() {
+    let alice = Account::new("alice".to_string());
+    let miner = Account::new("miner".to_string());
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    blockchain.params.subsidy = SubsidySchedule::new(40, 2);
+
+    blockchain.mine_block(miner.public_key).unwrap();
+    assert_eq!(blockchain.tip().unwrap().unwrap().data[0].nonce, 1);
+    assert!(blockchain.tip().unwrap().unwrap().data[0].is_coinbase());
+    assert_eq!(blockchain.balance(&miner.public_key).unwrap(), 40);
+
+    // Mined coins can be spent, and the reward halves on schedule
+    blockchain
+        .add_transaction(transfer(&miner, &alice, 15, 0))
+        .unwrap();
+    blockchain.mine_block(miner.public_key).unwrap();
+    assert_eq!(blockchain.balance(&miner.public_key).unwrap(), 45);
+    assert_eq!(blockchain.balance(&alice.public_key).unwrap(), 15);
+
+    assert_eq!(
+        blockchain.add_transaction(Transaction::coinbase(alice.public_key, 1, 0)),
+        Err(TransactionError::UnexpectedCoinbase)
+    );
+}
+
+#[test]
+fn malformed_coinbases_are_rejected() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let miner = Account::new("miner".to_string());
+    let mut params = ChainParams::regtest();
+    params.premine = vec![(alice.public_key, 100)];
+    let mut blockchain = Blockchain::new(params);
+    let subsidy = blockchain.params.subsidy.subsidy_at(1);
+    let coinbase = |amount, height| Transaction::coinbase(miner.public_key, amount, height);
+    let payment = transfer(&alice, &bob, 10, 0);
+
+    let mut signed = coinbase(subsidy, 1);
+    signed.signature = Some([1; 64]);
+
+    let cases = [
+        (vec![], BlockError::MissingCoinbase),
+        (vec![payment.clone()], BlockError::MissingCoinbase),
+        (
+            vec![coinbase(subsidy, 1), coinbase(1, 1)],
+            BlockError::InvalidTransaction(1, TransactionError::UnexpectedCoinbase),
+        ),
+        (
+            vec![coinbase(subsidy, 2)],
+            BlockError::InvalidCoinbaseHeight {
+                expected: 1,
+                found: 2,
+            },
+        ),
+        (
+            vec![coinbase(subsidy + 1, 1), payment],
+            BlockError::CoinbaseTooLarge {
+                allowed: subsidy,
+                found: subsidy + 1,
+            },
+        ),
+        (
+            vec![signed],
+            BlockError::InvalidTransaction(0, TransactionError::InvalidSignature),
+        ),
+    ];
+    for (data, error) in cases {
+        assert_eq!(
+            blockchain.add_block(with_body(&blockchain, data)),
+            Err(error)
+        );
+    }
+    assert_eq!(blockchain.chain.len(), 1);
+
+    // Claiming less than allowed is fine
+    let block = with_body(&blockchain, vec![coinbase(1, 1)]);
+    blockchain.add_block(block).unwrap();
+    assert_eq!(blockchain.balance(&miner.public_key).unwrap(), 1);
+}
@@ -0,0 +1,126 @@
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::Block;
+use rust_blockchain::chain::blockchain::Blockchain;
+use rust_blockchain::chain::storage::StorageError;
+use rust_blockchain::chain::transaction::Transaction;
+
+// Signed transfer with no fee
+pub fn transfer(from: &Account, to: &Account, amount: u64, nonce: u64) -> Transaction {
+    transfer_with_fee(from, to, amount, 0, nonce)
+}
+
+pub fn transfer_with_fee(
+    from: &Account,
+    to: &Account,
+    amount: u64,
+    fee: u64,
+    nonce: u64,
+) -> Transaction {
+    let mut tx = Transaction::new(to.public_key, from.public_key, amount, fee, nonce);
+    tx.sign(from).unwrap();
+    tx
+}
+
+// Solved child of `parent` paying the subsidy to `miner`, followed by `data`
+// and stamped a second after it
+pub fn child(
+    blockchain: &Blockchain,
+    parent: &Block,
+    miner: &Account,
+    data: Vec<Transaction>,
+) -> Block {
+    let genesis = blockchain.header_at(0).unwrap().timestamp;
+    child_at(
+        blockchain,
+        parent.hash(),
+        miner,
+        data,
+        parent.timestamp + 1 - genesis,
+    )
+}
+
+// Solved child of `parent` paying the subsidy to `miner`, stamped `offset`
+// seconds after genesis. The state root is filled in whenever the body
+// applies on that branch.
+pub fn child_at(
+    blockchain: &Blockchain,
+    parent: [u8; 32],
+    miner: &Account,
+    data: Vec<Transaction>,
+    offset: u64,
+) -> Block {
+    let index = blockchain.tree.get(&parent).unwrap().height + 1;
+    let mut body = vec![Transaction::coinbase(
+        miner.public_key,
+        blockchain.params.subsidy.subsidy_at(index),
+        index,
+    )];
+    body.extend(data);
+
+    let mut block = Block::new(
+        index,
+        parent,
+        blockchain.expected_bits(&parent).unwrap(),
+        body,
+    );
+    block.timestamp = blockchain.header_at(0).unwrap().timestamp + offset;
+    block.state_root = blockchain
+        .state_root_after(&parent, &block.data)
+        .unwrap_or_default();
+    solve(block)
+}
+
+pub fn solve(mut block: Block) -> Block {
+    while !block.meets_target() {
+        block.nonce += 1;
+    }
+    block
+}
+
+// Add `count` empty blocks on top of `parent`, stamped from `offset`
+// seconds after genesis, returning their hashes
+pub fn extend(
+    blockchain: &mut Blockchain,
+    parent: [u8; 32],
+    miner: &Account,
+    count: u64,
+    offset: u64,
+) -> Vec<[u8; 32]> {
+    let mut hashes = Vec::new();
+    let mut parent = parent;
+    for i in 0..count {
+        let block = child_at(blockchain, parent, miner, vec![], offset + i);
+        parent = block.hash();
+        blockchain.add_block(block).unwrap();
+        hashes.push(parent);
+    }
+    hashes
+}
+
+// Empty scratch directory unique to this test
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rust_blockchain-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// sled releases its file lock from background threads shortly after the
+// database is dropped, so a store reopened straight away by the same test
+// can still find it locked
+pub fn unlocked<T>(open: impl Fn() -> Result<T, StorageError>) -> Result<T, StorageError> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match open() {
+            Err(StorageError::Locked) if Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(10));
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::transfer;
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::Block;
+use rust_blockchain::chain::blockchain::Blockchain;
+use rust_blockchain::chain::encoding::{ENCODING_VERSION, EncodingError};
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::transaction::{Transaction, TransactionError};
+
+#[test]
+fn values_round_trip() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+
+    let tx = transfer(&alice, &bob, 5, 3);
+    assert_eq!(Transaction::decode(&tx.encode()), Ok(tx.clone()));
+
+    let unsigned = Transaction::new(bob.public_key, alice.public_key, 5, 0, 0);
+    assert_eq!(Transaction::decode(&unsigned.encode()), Ok(unsigned));
+
+    let block = Block::new(
+        1,
+        [3; 32],
+        0x207fffff,
+        vec![tx, transfer(&bob, &alice, 2, 0)],
+    );
+    assert_eq!(Block::decode(&block.encode()), Ok(block.clone()));
+}
+
+#[test]
+fn encoding_is_fixed_width_and_versioned() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+
+    let small = transfer(&alice, &bob, 1, 0);
+    let large = transfer(&alice, &bob, u64::MAX, u64::MAX);
+    assert_eq!(small.encode().len(), large.encode().len());
+    assert_eq!(small.encode()[0], ENCODING_VERSION);
+}
+
+#[test]
+fn malformed_bytes_are_rejected() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let bytes = transfer(&alice, &bob, 5, 0).encode();
+
+    assert_eq!(Transaction::decode(&[]), Err(EncodingError::Empty));
+
+    let mut other_version = bytes.clone();
+    other_version[0] = ENCODING_VERSION + 1;
+    assert_eq!(
+        Transaction::decode(&other_version),
+        Err(EncodingError::UnsupportedVersion(ENCODING_VERSION + 1))
+    );
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(matches!(
+        Transaction::decode(&trailing),
+        Err(EncodingError::Malformed(_))
+    ));
+
+    assert!(matches!(
+        Transaction::decode(&bytes[..bytes.len() - 1]),
+        Err(EncodingError::Malformed(_))
+    ));
+}
+
+#[test]
+fn txid_commits_to_the_signed_encoding() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+
+    let tx = transfer(&alice, &bob, 5, 0);
+    assert_eq!(tx.txid(), tx.clone().txid());
+    assert_ne!(tx.txid(), transfer(&alice, &bob, 5, 1).txid());
+
+    let mut unsigned = tx.clone();
+    unsigned.signature = None;
+    assert_ne!(unsigned.txid(), tx.txid());
+
+    // The mempool holds each transaction once
+    let mut params = ChainParams::regtest();
+    params.premine = vec![(alice.public_key, 5)];
+    let mut blockchain = Blockchain::new(params);
+    blockchain.add_transaction(tx.clone()).unwrap();
+    assert_eq!(
+        blockchain.add_transaction(tx),
+        Err(TransactionError::AlreadyInMempool)
+    );
+    assert_eq!(blockchain.mempool.len(), 1);
+}
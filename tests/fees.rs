@@ -0,0 +1,144 @@
+mod common;
+
+use common::transfer_with_fee;
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::Block;
+use rust_blockchain::chain::blockchain::Blockchain;
+use rust_blockchain::chain::mempool::Mempool;
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::transaction::{Transaction, TransactionError};
+
+fn funded(accounts: &[Account], balance: u64) -> Blockchain {
+    let mut params = ChainParams::regtest();
+    params.premine = accounts
+        .iter()
+        .map(|account| (account.public_key, balance))
+        .collect();
+    Blockchain::new(params)
+}
+
+#[test]
+fn full_mempool_evicts_the_lowest_fee_rate() {
+    let senders: Vec<_> = (0..3)
+        .map(|i| Account::new(format!("sender {i}")))
+        .collect();
+    let miner = Account::new("miner".to_string());
+    let mut blockchain = funded(&senders, 1000);
+    let size = transfer_with_fee(&senders[0], &miner, 1, 1, 0)
+        .encode()
+        .len();
+    blockchain.mempool = Mempool::new(size * 3);
+
+    blockchain
+        .add_transaction(transfer_with_fee(&senders[0], &miner, 1, 5, 0))
+        .unwrap();
+    blockchain
+        .add_transaction(transfer_with_fee(&senders[0], &miner, 1, 1, 1))
+        .unwrap();
+    blockchain
+        .add_transaction(transfer_with_fee(&senders[1], &miner, 1, 3, 0))
+        .unwrap();
+
+    assert_eq!(
+        blockchain.add_transaction(transfer_with_fee(&senders[2], &miner, 1, 1, 0)),
+        Err(TransactionError::MempoolFull)
+    );
+    blockchain
+        .add_transaction(transfer_with_fee(&senders[2], &miner, 1, 4, 0))
+        .unwrap();
+    assert_eq!(blockchain.mempool.len(), 3);
+    assert_eq!(blockchain.mempool.size(), size * 3);
+    assert!(blockchain.mempool.get(&senders[0].public_key, 1).is_none());
+
+    // A sender's earlier nonce is never evicted to make room for a later one
+    let mut pool = Mempool::new(size);
+    pool.insert(transfer_with_fee(&senders[0], &miner, 1, 1, 0))
+        .unwrap();
+    assert_eq!(
+        pool.insert(transfer_with_fee(&senders[0], &miner, 1, 9, 1)),
+        Err(TransactionError::MempoolFull)
+    );
+}
+
+#[test]
+fn template_takes_the_best_paying_transactions() {
+    let senders: Vec<_> = (0..3)
+        .map(|i| Account::new(format!("sender {i}")))
+        .collect();
+    let miner = Account::new("miner".to_string());
+    let mut blockchain = funded(&senders, 1000);
+
+    blockchain
+        .add_transaction(transfer_with_fee(&senders[0], &miner, 1, 2, 0))
+        .unwrap();
+    blockchain
+        .add_transaction(transfer_with_fee(&senders[0], &miner, 1, 9, 1))
+        .unwrap();
+    blockchain
+        .add_transaction(transfer_with_fee(&senders[1], &miner, 1, 5, 0))
+        .unwrap();
+    blockchain
+        .add_transaction(transfer_with_fee(&senders[2], &miner, 1, 1, 0))
+        .unwrap();
+
+    blockchain.mine_block(miner.public_key).unwrap();
+    let block = blockchain.tip().unwrap().unwrap();
+    let fees: Vec<u64> = block.data.iter().map(|tx| tx.fee).collect();
+    assert_eq!(fees, vec![0, 5, 2, 9, 1]);
+    assert_eq!(
+        block.data[0].amount,
+        blockchain.params.subsidy.subsidy_at(1) + 17
+    );
+    assert_eq!(blockchain.balance(&senders[0].public_key).unwrap(), 987);
+}
+
+#[test]
+fn template_respects_the_block_size_limit() {
+    let senders: Vec<_> = (0..2)
+        .map(|i| Account::new(format!("sender {i}")))
+        .collect();
+    let miner = Account::new("miner".to_string());
+    let mut blockchain = funded(&senders, 1000);
+
+    let low = transfer_with_fee(&senders[0], &miner, 1, 1, 0);
+    let size = low.encode().len();
+    blockchain.add_transaction(low).unwrap();
+    blockchain
+        .add_transaction(transfer_with_fee(&senders[1], &miner, 1, 9, 0))
+        .unwrap();
+
+    let overhead = Block::new(
+        1,
+        [0; 32],
+        0x207fffff,
+        vec![Transaction::coinbase(miner.public_key, 1, 1)],
+    )
+    .encode()
+    .len();
+    blockchain.max_block_size = overhead + size;
+    blockchain.mine_block(miner.public_key).unwrap();
+
+    let block = blockchain.tip().unwrap().unwrap();
+    assert_eq!(block.data.len(), 2);
+    assert_eq!(block.data[1].fee, 9);
+    assert_eq!(blockchain.mempool.len(), 1);
+}
+
+#[test]
+fn fees_count_towards_the_cost() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let mut blockchain = funded(std::slice::from_ref(&alice), 10);
+
+    assert_eq!(
+        blockchain.add_transaction(transfer_with_fee(&alice, &bob, 8, 3, 0)),
+        Err(TransactionError::InsufficientFunds {
+            available: 10,
+            required: 11
+        })
+    );
+    assert_eq!(
+        transfer_with_fee(&alice, &bob, u64::MAX, 1, 0).total_cost(),
+        Err(TransactionError::BalanceOverflow)
+    );
+}
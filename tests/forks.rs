@@ -0,0 +1,84 @@
+mod common;
+
+use common::{child_at, extend, temp_dir, transfer, unlocked};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::params::ChainParams;
+
+#[test]
+fn heaviest_branch_becomes_active() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let genesis = blockchain.chain[0];
+
+    let a1 = extend(&mut blockchain, genesis, &alice, 1, 1)[0];
+    let b1 = child_at(&blockchain, genesis, &bob, vec![], 2);
+    blockchain.add_block(b1.clone()).unwrap();
+
+    // Equal work keeps the branch that arrived first
+    assert_eq!(*blockchain.tip_hash(), a1);
+    assert_eq!(blockchain.tree.len(), 3);
+    assert_eq!(blockchain.tree.tips().count(), 2);
+
+    let b2 = extend(&mut blockchain, b1.hash(), &bob, 1, 3)[0];
+    assert_eq!(*blockchain.tip_hash(), b2);
+    assert_eq!(blockchain.balance(&alice.public_key).unwrap(), 0);
+    assert!(blockchain.balance(&bob.public_key).unwrap() > 0);
+    assert_eq!(blockchain.add_block(b1), Err(BlockError::AlreadyKnown));
+}
+
+#[test]
+fn descendants_of_an_invalid_block_are_rejected() {
+    let dir = temp_dir("forks-invalid");
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let carol = Account::new("carol".to_string());
+
+    let (bad, b2, b3);
+    {
+        let mut blockchain = Blockchain::open(ChainParams::regtest(), &dir).unwrap();
+        let genesis = blockchain.chain[0];
+        let active = extend(&mut blockchain, genesis, &alice, 2, 1);
+
+        // Carol has nothing to spend, so this branch can't connect
+        let spend = transfer(&carol, &alice, 1, 0);
+        let block = child_at(&blockchain, genesis, &bob, vec![spend], 10);
+        bad = block.hash();
+        blockchain.add_block(block).unwrap();
+        let block = child_at(&blockchain, bad, &bob, vec![], 11);
+        b2 = block.hash();
+        blockchain.add_block(block).unwrap();
+
+        // Outweighing the active chain makes the branch fail to connect
+        let block = child_at(&blockchain, b2, &bob, vec![], 12);
+        b3 = block.hash();
+        assert!(matches!(
+            blockchain.add_block(block),
+            Err(BlockError::InvalidTransaction(1, _))
+        ));
+        assert_eq!(blockchain.tip_hash(), &active[1]);
+        for hash in [bad, b2, b3] {
+            assert!(blockchain.tree.get(&hash).unwrap().invalid);
+        }
+
+        // Neither a grandchild nor anything further up is accepted
+        let sibling = child_at(&blockchain, b2, &bob, vec![], 13);
+        assert_eq!(
+            blockchain.add_block(sibling),
+            Err(BlockError::InvalidParent)
+        );
+        let b4 = child_at(&blockchain, b3, &bob, vec![], 14);
+        assert_eq!(blockchain.add_block(b4), Err(BlockError::InvalidParent));
+        assert_eq!(blockchain.tip_hash(), &active[1]);
+    }
+
+    let mut blockchain = unlocked(|| Blockchain::open(ChainParams::regtest(), &dir)).unwrap();
+    for hash in [bad, b2, b3] {
+        assert!(blockchain.tree.get(&hash).unwrap().invalid);
+    }
+    let b4 = child_at(&blockchain, b3, &bob, vec![], 14);
+    assert_eq!(blockchain.add_block(b4), Err(BlockError::InvalidParent));
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::{child, temp_dir, transfer, unlocked};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::Blockchain;
+use rust_blockchain::chain::index::{IndexOptions, TxLocation};
+use rust_blockchain::chain::params::ChainParams;
+
+// Compare every index against a scan of the active chain
+fn check_indexes(blockchain: &Blockchain, accounts: &[&Account]) {
+    let mut history: HashMap<[u8; 33], Vec<[u8; 32]>> = HashMap::new();
+    for height in 0..=blockchain.height() {
+        let block = blockchain.block_at(height).unwrap().unwrap();
+        assert_eq!(
+            blockchain.block_height(&block.hash()).unwrap(),
+            Some(height)
+        );
+        for (position, tx) in block.data.iter().enumerate() {
+            let (found, location) = blockchain.get_transaction(&tx.txid()).unwrap().unwrap();
+            assert_eq!(&found, tx);
+            assert_eq!(
+                location,
+                TxLocation {
+                    block: block.hash(),
+                    height,
+                    position: position as u32
+                }
+            );
+            history.entry(tx.recipient).or_default().push(tx.txid());
+            if !tx.is_coinbase() && tx.sender != tx.recipient {
+                history.entry(tx.sender).or_default().push(tx.txid());
+            }
+        }
+    }
+
+    for account in accounts {
+        let txids: Vec<_> = blockchain
+            .address_history(&account.public_key)
+            .unwrap()
+            .into_iter()
+            .map(|(txid, _)| txid)
+            .collect();
+        assert_eq!(
+            txids,
+            history.remove(&account.public_key).unwrap_or_default()
+        );
+    }
+}
+
+// Enable the indexes part way up a chain, then reorg under them
+fn follows_a_reorg(mut blockchain: Blockchain, miner: &Account, alice: &Account) -> Blockchain {
+    let genesis = blockchain.block_at(0).unwrap().unwrap();
+
+    let a1 = child(&blockchain, &genesis, miner, vec![]);
+    blockchain.add_block(a1.clone()).unwrap();
+    assert_eq!(blockchain.block_height(&a1.hash()).unwrap(), None);
+
+    // Turning the indexes on fills them in for blocks already connected
+    blockchain.set_indexes(IndexOptions::all()).unwrap();
+    check_indexes(&blockchain, &[miner, alice]);
+
+    let payment = transfer(miner, alice, 3, 0);
+    let a2 = child(&blockchain, &a1, miner, vec![payment.clone()]);
+    blockchain.add_block(a2.clone()).unwrap();
+    check_indexes(&blockchain, &[miner, alice]);
+    assert_eq!(
+        blockchain.address_history(&alice.public_key).unwrap().len(),
+        1
+    );
+
+    let b1 = child(&blockchain, &a1, alice, vec![]);
+    blockchain.add_block(b1.clone()).unwrap();
+    let b2 = child(&blockchain, &b1, alice, vec![]);
+    blockchain.add_block(b2.clone()).unwrap();
+    assert_eq!(*blockchain.tip_hash(), b2.hash());
+
+    // Entries for the disconnected branch are gone
+    check_indexes(&blockchain, &[miner, alice]);
+    assert_eq!(blockchain.block_height(&a2.hash()).unwrap(), None);
+    assert!(
+        blockchain
+            .get_transaction(&payment.txid())
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(
+        blockchain.address_history(&alice.public_key).unwrap().len(),
+        2
+    );
+    blockchain
+}
+
+#[test]
+fn indexes_follow_the_active_chain_in_memory() {
+    let miner = Account::new("miner".to_string());
+    let alice = Account::new("alice".to_string());
+    follows_a_reorg(Blockchain::new(ChainParams::regtest()), &miner, &alice);
+}
+
+#[test]
+fn disabled_indexes_are_deleted() {
+    let miner = Account::new("miner".to_string());
+    let alice = Account::new("alice".to_string());
+    let mut blockchain = follows_a_reorg(Blockchain::new(ChainParams::regtest()), &miner, &alice);
+    let tip = blockchain.block_at(blockchain.height()).unwrap().unwrap();
+
+    blockchain
+        .set_indexes(IndexOptions {
+            addresses: true,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(blockchain.block_height(&tip.hash()).unwrap(), None);
+    assert_eq!(
+        blockchain.store.get_block_height(&tip.hash()).unwrap(),
+        None
+    );
+    assert_eq!(
+        blockchain
+            .store
+            .get_tx_location(&tip.data[0].txid())
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        blockchain.address_history(&alice.public_key).unwrap().len(),
+        2
+    );
+}
+
+#[test]
+fn index_setting_is_kept_on_disk() {
+    let dir = temp_dir("indexes");
+    let params = ChainParams::regtest();
+    let miner = Account::new("miner".to_string());
+    let alice = Account::new("alice".to_string());
+
+    let blockchain = follows_a_reorg(
+        Blockchain::open(params.clone(), &dir).unwrap(),
+        &miner,
+        &alice,
+    );
+    drop(blockchain);
+
+    let mut blockchain = unlocked(|| Blockchain::open(params.clone(), &dir)).unwrap();
+    assert_eq!(blockchain.indexes(), IndexOptions::all());
+    check_indexes(&blockchain, &[&miner, &alice]);
+
+    let only_addresses = IndexOptions {
+        addresses: true,
+        ..Default::default()
+    };
+    blockchain.set_indexes(only_addresses).unwrap();
+    drop(blockchain);
+
+    let blockchain = unlocked(|| Blockchain::open(params.clone(), &dir)).unwrap();
+    assert_eq!(blockchain.indexes(), only_addresses);
+    assert_eq!(
+        blockchain.address_history(&alice.public_key).unwrap().len(),
+        2
+    );
+    drop(blockchain);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
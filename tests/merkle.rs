@@ -0,0 +1,51 @@
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::Block;
+use rust_blockchain::chain::encoding;
+use rust_blockchain::chain::merkle;
+use rust_blockchain::chain::transaction::Transaction;
+
+#[test]
+fn every_transaction_has_an_inclusion_proof() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+
+    for count in 1..=7 {
+        let txs: Vec<_> = (1..=count)
+            .map(|amount| Transaction::new(bob.public_key, alice.public_key, amount, 0, 0))
+            .collect();
+        let block = Block::new(1, [0; 32], 0x207fffff, txs.clone());
+
+        for (i, tx) in txs.iter().enumerate() {
+            let proof = block.merkle_proof(i).unwrap();
+            assert!(tx.verify_inclusion(&proof, block.merkle_root));
+            if count > 1 {
+                assert!(!txs[(i + 1) % txs.len()].verify_inclusion(&proof, block.merkle_root));
+            }
+
+            // Proofs travel to light clients in the canonical encoding
+            let decoded: merkle::MerkleProof = encoding::decode(&encoding::encode(&proof)).unwrap();
+            assert_eq!(decoded, proof);
+
+            let mut forged = proof.clone();
+            if let Some(sibling) = forged.siblings.first_mut() {
+                sibling[0] ^= 1;
+                assert!(!tx.verify_inclusion(&forged, block.merkle_root));
+            }
+        }
+        assert!(block.merkle_proof(txs.len()).is_none());
+    }
+}
+
+#[test]
+fn root_covers_every_leaf() {
+    assert_eq!(merkle::merkle_root(&[]), [0; 32]);
+    assert_eq!(merkle::merkle_root(&[[5; 32]]), [5; 32]);
+
+    let leaves = [[1; 32], [2; 32], [3; 32]];
+    let root = merkle::merkle_root(&leaves);
+    for i in 0..leaves.len() {
+        let mut changed = leaves;
+        changed[i][0] ^= 1;
+        assert_ne!(merkle::merkle_root(&changed), root);
+    }
+}
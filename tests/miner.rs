@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use std::thread;
+
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::Block;
+use rust_blockchain::chain::blockchain::Blockchain;
+use rust_blockchain::chain::clock::{MockClock, SystemClock};
+use rust_blockchain::chain::miner::Miner;
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::transaction::Transaction;
+
+fn block(bits: u32) -> Block {
+    let miner = Account::new("miner".to_string());
+    Block::new(
+        1,
+        [0; 32],
+        bits,
+        vec![Transaction::coinbase(miner.public_key, 1, 1)],
+    )
+}
+
+#[test]
+fn threads_share_the_nonce_space() {
+    for threads in [1, 4] {
+        let miner = Miner::new(threads);
+        let solved = miner.mine(block(0x2000ffff), &SystemClock).unwrap();
+        assert!(solved.meets_target());
+        assert!(miner.hashrate() > 0.0);
+    }
+    assert_eq!(Miner::new(0).threads, 1);
+}
+
+#[test]
+fn exhausted_nonces_bump_the_timestamp() {
+    let mut miner = Miner::new(2);
+    miner.max_nonce = 10;
+    let template = block(0x2000ffff);
+
+    // Bumps come from the clock passed in, not the wall clock
+    let later = template.timestamp + 1_000;
+    let solved = miner
+        .mine(template.clone(), &MockClock::new(later))
+        .unwrap();
+    assert!(solved.meets_target());
+    assert!(solved.nonce <= 10);
+    assert!(solved.timestamp == template.timestamp || solved.timestamp >= later);
+    assert_eq!(solved.data, template.data);
+}
+
+#[test]
+fn cancelled_search_returns_nothing() {
+    let miner = Arc::new(Miner::new(2));
+    let handle = miner.cancel_handle();
+
+    // A target this hard won't be met before the cancel. Hashes are only
+    // counted once the search is under way and polling the handle.
+    let worker = {
+        let miner = miner.clone();
+        thread::spawn(move || miner.mine(block(0x1000ffff), &SystemClock))
+    };
+    while miner.hashrate() == 0.0 {
+        thread::yield_now();
+    }
+    handle.cancel();
+    assert!(worker.join().unwrap().is_none());
+
+    // A new search starts from a clear flag
+    assert!(miner.mine(block(0x207fffff), &SystemClock).is_some());
+}
+
+#[test]
+fn cancel_before_search_is_honoured() {
+    let miner = Miner::new(2);
+
+    // E.g. a competing block arrived while the template was being built
+    miner.cancel_handle().cancel();
+    assert!(miner.mine(block(0x207fffff), &SystemClock).is_none());
+    assert!(miner.mine(block(0x207fffff), &SystemClock).is_some());
+}
+
+#[test]
+fn mined_blocks_are_connected() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    blockchain.miner = Miner::new(2);
+    let miner = Account::new("miner".to_string());
+
+    let hash = blockchain.mine_block(miner.public_key).unwrap().unwrap();
+    assert_eq!(blockchain.tip().unwrap().unwrap().hash(), hash);
+    assert_eq!(
+        blockchain.balance(&miner.public_key).unwrap(),
+        blockchain.params.subsidy.subsidy_at(1)
+    );
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use common::{child, transfer};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain, MAX_NONCE_GAP};
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::transaction::TransactionError;
+
+fn funded(account: &Account, balance: u64) -> Blockchain {
+    let mut params = ChainParams::regtest();
+    params.premine = vec![(account.public_key, balance)];
+    Blockchain::new(params)
+}
+
+#[test]
+fn confirmed_transactions_cannot_be_replayed() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let miner = Account::new("miner".to_string());
+    let mut blockchain = funded(&alice, 100);
+
+    let payment = transfer(&alice, &bob, 5, 0);
+    blockchain.add_transaction(payment.clone()).unwrap();
+    assert_eq!(
+        blockchain.add_transaction(payment.clone()),
+        Err(TransactionError::AlreadyInMempool)
+    );
+    let block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        vec![payment.clone()],
+    );
+    blockchain.add_block(block).unwrap();
+    assert_eq!(blockchain.nonce(&alice.public_key).unwrap(), 1);
+    assert!(blockchain.mempool.is_empty());
+
+    assert_eq!(
+        blockchain.add_transaction(payment.clone()),
+        Err(TransactionError::NonceTooLow {
+            expected: 1,
+            found: 0
+        })
+    );
+
+    let block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        vec![payment],
+    );
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::InvalidTransaction(
+            1,
+            TransactionError::NonceTooLow {
+                expected: 1,
+                found: 0
+            }
+        ))
+    );
+    assert_eq!(blockchain.balance(&bob.public_key).unwrap(), 5);
+}
+
+#[test]
+fn blocks_must_use_the_next_nonce() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let miner = Account::new("miner".to_string());
+    let mut blockchain = funded(&alice, 100);
+
+    let block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        vec![transfer(&alice, &bob, 5, 1)],
+    );
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::InvalidTransaction(
+            1,
+            TransactionError::NonceTooHigh {
+                expected: 0,
+                found: 1
+            }
+        ))
+    );
+
+    let payments = vec![transfer(&alice, &bob, 5, 0), transfer(&alice, &bob, 5, 1)];
+    let block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        payments,
+    );
+    blockchain.add_block(block).unwrap();
+    assert_eq!(blockchain.nonce(&alice.public_key).unwrap(), 2);
+}
+
+#[test]
+fn mempool_holds_a_bounded_nonce_gap() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let miner = Account::new("miner".to_string());
+    let mut blockchain = funded(&alice, 100);
+
+    assert_eq!(
+        blockchain.add_transaction(transfer(&alice, &bob, 1, MAX_NONCE_GAP + 1)),
+        Err(TransactionError::NonceTooHigh {
+            expected: 0,
+            found: MAX_NONCE_GAP + 1
+        })
+    );
+
+    // A transaction waiting on an earlier nonce stays queued
+    blockchain
+        .add_transaction(transfer(&alice, &bob, 1, 1))
+        .unwrap();
+    assert_eq!(
+        blockchain.add_transaction(transfer(&alice, &bob, 2, 1)),
+        Err(TransactionError::NonceAlreadyPending)
+    );
+    blockchain.mine_block(miner.public_key).unwrap();
+    assert_eq!(blockchain.tip().unwrap().unwrap().data.len(), 1);
+    assert_eq!(blockchain.mempool.len(), 1);
+
+    blockchain
+        .add_transaction(transfer(&alice, &bob, 1, 0))
+        .unwrap();
+    blockchain.mine_block(miner.public_key).unwrap();
+    assert_eq!(blockchain.tip().unwrap().unwrap().data.len(), 3);
+    assert!(blockchain.mempool.is_empty());
+    assert_eq!(blockchain.nonce(&alice.public_key).unwrap(), 2);
+}
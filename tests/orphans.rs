@@ -0,0 +1,79 @@
+mod common;
+
+use common::{child_at, solve};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::Block;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::orphans::OrphanPool;
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::transaction::Transaction;
+
+// Blocks 1 to `count` of a chain built elsewhere
+fn foreign_blocks(count: u64) -> Vec<Block> {
+    let miner = Account::new("miner".to_string());
+    let mut other = Blockchain::new(ChainParams::regtest());
+    let mut blocks = Vec::new();
+    for i in 0..count {
+        let block = child_at(&other, *other.tip_hash(), &miner, vec![], 10 + i);
+        other.add_block(block.clone()).unwrap();
+        blocks.push(block);
+    }
+    blocks
+}
+
+#[test]
+fn orphans_connect_once_their_parent_arrives() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let blocks = foreign_blocks(4);
+
+    for block in blocks[1..].iter().rev() {
+        assert_eq!(
+            blockchain.add_block(block.clone()),
+            Err(BlockError::UnknownParent)
+        );
+    }
+    assert_eq!(
+        blockchain.add_block(blocks[3].clone()),
+        Err(BlockError::AlreadyKnown)
+    );
+    assert_eq!(blockchain.orphans.len(), 3);
+    assert_eq!(blockchain.missing_blocks(), vec![blocks[0].hash()]);
+
+    blockchain.add_block(blocks[0].clone()).unwrap();
+    assert_eq!(*blockchain.tip_hash(), blocks[3].hash());
+    assert!(blockchain.orphans.is_empty());
+    assert!(blockchain.missing_blocks().is_empty());
+}
+
+#[test]
+fn orphan_pool_is_bounded() {
+    let blocks = foreign_blocks(4);
+    let mut pool = OrphanPool::new(2, 10);
+
+    assert!(pool.insert(blocks[1].clone(), 0));
+    assert!(pool.insert(blocks[2].clone(), 1));
+    assert!(!pool.insert(blocks[2].clone(), 1));
+    assert!(pool.insert(blocks[3].clone(), 2));
+    assert_eq!(pool.len(), 2);
+    assert!(!pool.contains(&blocks[1].hash()));
+
+    pool.expire(12);
+    assert_eq!(pool.len(), 1);
+    pool.remove_descendants(&blocks[2].hash());
+    assert!(pool.is_empty());
+}
+
+#[test]
+fn orphans_easier_than_the_proof_of_work_limit_are_refused() {
+    let mut blockchain = Blockchain::new(ChainParams::mainnet());
+    let miner = Account::new("miner".to_string());
+
+    // Trivial to solve, but far easier than mainnet ever allows
+    let coinbase = Transaction::coinbase(miner.public_key, 1, 5);
+    let block = solve(Block::new(5, [9; 32], 0x207fffff, vec![coinbase]));
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::InsufficientWork)
+    );
+    assert!(blockchain.orphans.is_empty());
+}
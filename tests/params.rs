@@ -0,0 +1,55 @@
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain, ChainError};
+use rust_blockchain::chain::params::ChainParams;
+
+#[test]
+fn every_network_has_its_own_valid_genesis() {
+    let networks = [
+        ChainParams::mainnet(),
+        ChainParams::testnet(),
+        ChainParams::regtest(),
+    ];
+
+    for params in &networks {
+        let genesis = params.genesis();
+        assert_eq!(genesis, params.genesis());
+        assert_eq!(genesis.index, 0);
+        assert_eq!(genesis.prev_hash, [0; 32]);
+        assert_eq!(genesis.timestamp, params.genesis_timestamp);
+        assert_eq!(genesis.bits, params.initial_bits);
+        assert!(genesis.meets_target());
+        assert_eq!(
+            ChainParams::from_name(params.name).unwrap().genesis(),
+            genesis
+        );
+    }
+
+    assert_ne!(networks[0].genesis().hash(), networks[1].genesis().hash());
+    assert_ne!(networks[0].magic, networks[1].magic);
+    assert!(ChainParams::from_name("nonexistent").is_none());
+}
+
+#[test]
+fn premine_is_paid_out_by_the_genesis_block() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let mut params = ChainParams::regtest();
+    params.premine = vec![(alice.public_key, 1000), (bob.public_key, 5)];
+
+    let blockchain = Blockchain::new(params.clone());
+    assert_eq!(blockchain.balance(&alice.public_key).unwrap(), 1000);
+    assert_eq!(blockchain.balance(&bob.public_key).unwrap(), 5);
+    assert_eq!(blockchain.block_at(0).unwrap().unwrap().data.len(), 2);
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+
+    // A chain exported under one set of params doesn't load under another
+    let blocks = Blockchain::decode_blocks(&blockchain.encode_blocks().unwrap()).unwrap();
+    assert!(Blockchain::from_blocks(params, blocks.clone()).is_ok());
+    assert!(matches!(
+        Blockchain::from_blocks(ChainParams::regtest(), blocks),
+        Err(ChainError::InvalidBlock {
+            height: 0,
+            error: BlockError::InvalidGenesis
+        })
+    ));
+}
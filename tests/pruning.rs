@@ -0,0 +1,149 @@
+mod common;
+
+use common::{child_at, extend, temp_dir, transfer, unlocked};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::disk_store::DiskStore;
+use rust_blockchain::chain::index::IndexOptions;
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::storage::StorageError;
+
+#[test]
+fn pruned_bodies_are_dropped_from_disk() {
+    let dir = temp_dir("pruning");
+    let params = ChainParams::regtest();
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+
+    let (pruned, side, tip);
+    {
+        let mut store = DiskStore::open(&dir, params.magic).unwrap();
+        store.blocks.segment_size = 400;
+        let mut blockchain = Blockchain::with_store(params.clone(), Box::new(store)).unwrap();
+        let genesis = blockchain.chain[0];
+
+        let a1 = extend(&mut blockchain, genesis, &alice, 1, 10)[0];
+        let block = child_at(
+            &blockchain,
+            a1,
+            &alice,
+            vec![transfer(&alice, &bob, 5, 0)],
+            11,
+        );
+        let a2 = block.hash();
+        blockchain.add_block(block).unwrap();
+        let a4 = extend(&mut blockchain, a2, &alice, 2, 12)[1];
+        side = extend(&mut blockchain, a1, &bob, 1, 30)[0];
+
+        blockchain.set_prune_depth(Some(2)).unwrap();
+        assert_eq!(blockchain.pruned_height(), 2);
+        pruned = blockchain.chain[1];
+        assert!(blockchain.serve_block(&pruned).unwrap().is_none());
+        assert!(blockchain.serve_block(&side).unwrap().is_none());
+        assert!(blockchain.serve_block(&genesis).unwrap().is_some());
+        assert!(blockchain.serve_block(&a4).unwrap().is_some());
+        assert_eq!(blockchain.validate_chain(), Ok(()));
+
+        // Pruning follows the tip
+        extend(&mut blockchain, a4, &alice, 6, 40);
+        assert_eq!(blockchain.height(), 10);
+        assert_eq!(blockchain.pruned_height(), 8);
+        assert_eq!(blockchain.validate_chain(), Ok(()));
+
+        // A reorg inside the window still works
+        let fork = blockchain.chain[9];
+        tip = extend(&mut blockchain, fork, &bob, 2, 60)[1];
+        assert_eq!(*blockchain.tip_hash(), tip);
+        assert_eq!(blockchain.pruned_height(), 9);
+        assert_eq!(
+            blockchain.balance(&bob.public_key).unwrap(),
+            5 + 2 * params.subsidy.subsidy_at(11)
+        );
+
+        // One forking below it is refused, however much work it has
+        let mut parent = blockchain.chain[5];
+        let mut result = Ok(());
+        for i in 0..9 {
+            let block = child_at(&blockchain, parent, &bob, vec![], 70 + i);
+            parent = block.hash();
+            result = blockchain.add_block(block);
+            if result.is_err() {
+                break;
+            }
+        }
+        assert_eq!(result, Err(BlockError::ReorgTooDeep { fork: 5, min: 9 }));
+        assert_eq!(*blockchain.tip_hash(), tip);
+        assert_eq!(blockchain.validate_chain(), Ok(()));
+    }
+
+    let blockchain = unlocked(|| Blockchain::open(params.clone(), &dir)).unwrap();
+    assert_eq!(*blockchain.tip_hash(), tip);
+    assert_eq!(blockchain.prune_depth(), Some(2));
+    assert_eq!(blockchain.pruned_height(), 9);
+    assert!(blockchain.get_block(&pruned).unwrap().is_none());
+    assert!(blockchain.get_block(&side).unwrap().is_none());
+    assert!(
+        blockchain
+            .serve_block(&blockchain.chain[0])
+            .unwrap()
+            .is_some()
+    );
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+    drop(blockchain);
+
+    // Segments left with no live block are deleted
+    let store = unlocked(|| DiskStore::open(&dir, params.magic)).unwrap();
+    let files = std::fs::read_dir(dir.join("blocks")).unwrap().count();
+    assert_eq!(files, store.blocks.segment_count().unwrap());
+    assert!(files < 10);
+    assert!(!store.blocks.contains(&pruned).unwrap());
+    assert!(store.blocks.contains(&tip).unwrap());
+    drop(store);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn memory_chain_prunes_too() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let alice = Account::new("alice".to_string());
+    let genesis = blockchain.chain[0];
+
+    blockchain.set_prune_depth(Some(1)).unwrap();
+    let hashes = extend(&mut blockchain, genesis, &alice, 3, 10);
+    assert_eq!(blockchain.pruned_height(), 2);
+    assert!(blockchain.get_block(&hashes[0]).unwrap().is_none());
+    assert!(blockchain.get_block(&hashes[2]).unwrap().is_some());
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+}
+
+#[test]
+fn indexes_and_export_need_every_body() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let alice = Account::new("alice".to_string());
+    let genesis = blockchain.chain[0];
+    let hashes = extend(&mut blockchain, genesis, &alice, 4, 10);
+
+    blockchain.set_prune_depth(Some(2)).unwrap();
+    assert_eq!(blockchain.pruned_height(), 2);
+
+    // The block index is built from headers, pruned heights included
+    let blocks = IndexOptions {
+        blocks: true,
+        ..IndexOptions::default()
+    };
+    blockchain.set_indexes(blocks).unwrap();
+    assert_eq!(blockchain.block_height(&hashes[0]).unwrap(), Some(1));
+    assert_eq!(blockchain.block_height(&hashes[3]).unwrap(), Some(4));
+
+    // A transaction index would be missing the pruned blocks
+    assert!(matches!(
+        blockchain.set_indexes(IndexOptions::all()),
+        Err(StorageError::MissingBody { height: 1 })
+    ));
+    assert_eq!(blockchain.indexes(), blocks);
+
+    assert!(matches!(
+        blockchain.encode_blocks(),
+        Err(StorageError::MissingBody { height: 1 })
+    ));
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::{child_at, extend, transfer};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::Blockchain;
+use rust_blockchain::chain::params::ChainParams;
+
+#[test]
+fn reorg_rolls_back_state_and_returns_transactions() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let miner = Account::new("miner".to_string());
+    let other = Account::new("other".to_string());
+    let payee = Account::new("payee".to_string());
+    let genesis = blockchain.chain[0];
+
+    let a1 = extend(&mut blockchain, genesis, &miner, 1, 1)[0];
+    let first = transfer(&miner, &payee, 10, 0);
+    let second = transfer(&miner, &payee, 20, 1);
+    let a2 = child_at(
+        &blockchain,
+        a1,
+        &other,
+        vec![first.clone(), second.clone()],
+        2,
+    );
+    blockchain.add_block(a2.clone()).unwrap();
+    let miner_before = blockchain.balance(&miner.public_key).unwrap();
+    assert_eq!(blockchain.balance(&payee.public_key).unwrap(), 30);
+
+    // A branch from a1 that only confirms the first transfer
+    let b2 = child_at(&blockchain, a1, &payee, vec![first.clone()], 3);
+    blockchain.add_block(b2.clone()).unwrap();
+    assert_eq!(*blockchain.tip_hash(), a2.hash());
+    let b3 = extend(&mut blockchain, b2.hash(), &payee, 1, 4)[0];
+
+    assert_eq!(*blockchain.tip_hash(), b3);
+    assert_eq!(blockchain.nonce(&miner.public_key).unwrap(), 1);
+    assert_eq!(blockchain.balance(&other.public_key).unwrap(), 0);
+    assert!(blockchain.mempool.contains(&second.txid()));
+    assert!(!blockchain.mempool.contains(&first.txid()));
+    assert!(blockchain.store.get_undo(&a2.hash()).unwrap().is_none());
+
+    // Switching back confirms the second transfer again
+    let a4 = *extend(&mut blockchain, a2.hash(), &other, 2, 5)
+        .last()
+        .unwrap();
+    assert_eq!(*blockchain.tip_hash(), a4);
+    assert_eq!(blockchain.balance(&miner.public_key).unwrap(), miner_before);
+    assert_eq!(blockchain.balance(&payee.public_key).unwrap(), 30);
+    assert!(blockchain.mempool.is_empty());
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+}
+
+#[test]
+fn reorg_evicts_transactions_that_lost_their_funds() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let miner = Account::new("miner".to_string());
+    let other = Account::new("other".to_string());
+    let payee = Account::new("payee".to_string());
+    let genesis = blockchain.chain[0];
+
+    // The spend is funded by a coinbase that the reorg takes away
+    extend(&mut blockchain, genesis, &miner, 1, 1);
+    let spend = transfer(&miner, &payee, 10, 0);
+    blockchain.add_transaction(spend.clone()).unwrap();
+    extend(&mut blockchain, genesis, &other, 2, 2);
+
+    assert_eq!(blockchain.balance(&miner.public_key).unwrap(), 0);
+    assert!(!blockchain.mempool.contains(&spend.txid()));
+
+    // The node can keep mining
+    blockchain.mine_block(other.public_key).unwrap();
+    blockchain.mine_block(other.public_key).unwrap();
+    assert_eq!(blockchain.height(), 4);
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+}
+
+#[test]
+fn block_template_skips_transactions_that_do_not_apply() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let miner = Account::new("miner".to_string());
+    let broke = Account::new("broke".to_string());
+    let payee = Account::new("payee".to_string());
+    let genesis = blockchain.chain[0];
+    extend(&mut blockchain, genesis, &miner, 1, 1);
+
+    let funded = transfer(&miner, &payee, 5, 0);
+    blockchain.add_transaction(funded.clone()).unwrap();
+    // Slipped past the mempool checks, along with a follow-up
+    blockchain
+        .mempool
+        .insert(transfer(&broke, &payee, 5, 0))
+        .unwrap();
+    blockchain
+        .mempool
+        .insert(transfer(&broke, &payee, 5, 1))
+        .unwrap();
+
+    let template = blockchain.block_template(miner.public_key).unwrap();
+    assert_eq!(template.data.len(), 2);
+    assert_eq!(template.data[1], funded);
+    assert_ne!(template.state_root, [0; 32]);
+
+    blockchain.mine_block(miner.public_key).unwrap();
+    assert_eq!(blockchain.balance(&payee.public_key).unwrap(), 5);
+}
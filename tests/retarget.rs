@@ -0,0 +1,136 @@
+mod common;
+
+use common::{child, solve};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::BlockHeader;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::retarget::{RetargetMode, RetargetParams};
+use rust_blockchain::chain::target::U256;
+
+const BITS: u32 = 0x1d00ffff;
+
+// Headers from genesis, each `spacing` seconds after the previous one
+fn headers(count: u64, spacing: u64) -> Vec<BlockHeader> {
+    (0..count)
+        .map(|height| BlockHeader {
+            index: height,
+            timestamp: 1_000_000 + height * spacing,
+            prev_hash: [0; 32],
+            merkle_root: [0; 32],
+            state_root: [0; 32],
+            bits: BITS,
+            nonce: 0,
+        })
+        .collect()
+}
+
+fn params(mode: RetargetMode) -> RetargetParams {
+    RetargetParams {
+        mode,
+        target_spacing: 600,
+        pow_limit: U256::from_compact(0x1d00ffff).unwrap(),
+    }
+}
+
+fn target(bits: u32) -> U256 {
+    U256::from_compact(bits).unwrap()
+}
+
+#[test]
+fn window_retargets_at_interval_boundaries() {
+    let params = params(RetargetMode::Window { interval: 4 });
+
+    // The window spans the three gaps between the first and last of its
+    // four blocks, so 400 second gaps take half the expected 2400 seconds
+    let fast = headers(8, 400);
+    assert_eq!(params.next_bits(3, |h| &fast[h as usize]), BITS);
+    let bits = params.next_bits(4, |h| &fast[h as usize]);
+    assert_eq!(target(bits), target(BITS).div_u64(2));
+
+    // Each step is limited to a factor of four
+    let instant = headers(8, 0);
+    let bits = params.next_bits(4, |h| &instant[h as usize]);
+    assert_eq!(target(bits), target(BITS).div_u64(4));
+
+    // Slow blocks can't make the target easier than the limit
+    let slow = headers(8, 6000);
+    assert_eq!(params.next_bits(4, |h| &slow[h as usize]), BITS);
+}
+
+#[test]
+fn lwma_retargets_every_block() {
+    let params = params(RetargetMode::Lwma { window: 4 });
+
+    let fast = headers(10, 300);
+    assert_eq!(params.next_bits(4, |h| &fast[h as usize]), BITS);
+    for height in 5..10 {
+        let bits = params.next_bits(height, |h| &fast[h as usize]);
+        assert!(target(bits) < target(BITS));
+    }
+
+    let steady = headers(10, 600);
+    let bits = params.next_bits(9, |h| &steady[h as usize]);
+    assert_eq!(bits, BITS);
+
+    // One wild timestamp moves the target only so far
+    let mut skewed = headers(10, 600);
+    skewed[8].timestamp += 1_000_000;
+    let bits = params.next_bits(9, |h| &skewed[h as usize]);
+    assert!(target(bits) <= target(BITS));
+}
+
+#[test]
+fn blocks_must_carry_the_expected_bits() {
+    let miner = Account::new("miner".to_string());
+
+    for mode in [
+        RetargetMode::Window { interval: 4 },
+        RetargetMode::Lwma { window: 4 },
+    ] {
+        let mut params = ChainParams::regtest();
+        params.initial_bits = 0x2000ffff;
+        params.retarget.mode = mode;
+        let mut blockchain = Blockchain::new(params);
+        let genesis = blockchain.tip().unwrap().unwrap().timestamp;
+
+        // Blocks one second apart push the target down
+        for offset in 1..=9 {
+            let mut block = child(
+                &blockchain,
+                &blockchain.tip().unwrap().unwrap(),
+                &miner,
+                vec![],
+            );
+            block.timestamp = genesis + offset;
+            blockchain.add_block(solve(block)).unwrap();
+        }
+        assert!(blockchain.target() < target(0x2000ffff));
+
+        let mut block = child(
+            &blockchain,
+            &blockchain.tip().unwrap().unwrap(),
+            &miner,
+            vec![],
+        );
+        block.bits = 0x2000ffff;
+        assert_eq!(
+            blockchain.add_block(solve(block)),
+            Err(BlockError::InvalidBits {
+                expected: blockchain.bits(),
+                found: 0x2000ffff
+            })
+        );
+    }
+}
+
+#[test]
+fn unknown_parent_has_no_expected_bits() {
+    let blockchain = Blockchain::new(ChainParams::regtest());
+
+    assert_eq!(
+        blockchain.expected_bits(&[7; 32]),
+        Err(BlockError::UnknownParent)
+    );
+    assert_eq!(blockchain.median_time_past(&[7; 32]), None);
+}
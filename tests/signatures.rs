@@ -0,0 +1,102 @@
+mod common;
+
+use common::{child, transfer};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::transaction::{Transaction, TransactionError};
+
+#[test]
+fn signature_covers_every_field() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+
+    let tx = transfer(&alice, &bob, 5, 0);
+    assert_eq!(tx.verify(), Ok(()));
+    assert_eq!(tx.validate(), Ok(()));
+
+    let changes: [fn(&mut Transaction); 5] = [
+        |tx| tx.recipient[1] ^= 1,
+        |tx| tx.sender = Account::new("mallory".to_string()).public_key,
+        |tx| tx.amount += 1,
+        |tx| tx.fee += 1,
+        |tx| tx.nonce += 1,
+    ];
+    for change in changes {
+        let mut forged = tx.clone();
+        change(&mut forged);
+        assert_eq!(forged.verify(), Err(TransactionError::InvalidSignature));
+    }
+
+    let mut forged = tx.clone();
+    forged.signature.as_mut().unwrap()[10] ^= 1;
+    assert_eq!(forged.verify(), Err(TransactionError::InvalidSignature));
+}
+
+#[test]
+fn only_the_sender_can_sign() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+
+    let mut tx = Transaction::new(bob.public_key, alice.public_key, 5, 0, 0);
+    assert_eq!(tx.verify(), Err(TransactionError::MissingSignature));
+    assert_eq!(tx.sign(&bob), Err(TransactionError::WrongSigner));
+    assert_eq!(tx.signature, None);
+
+    let mut tx = Transaction::new(bob.public_key, [9; 33], 5, 0, 0);
+    tx.signature = Some([1; 64]);
+    assert_eq!(tx.verify(), Err(TransactionError::InvalidPublicKey));
+}
+
+#[test]
+fn unsigned_transactions_are_refused() {
+    let alice = Account::new("alice".to_string());
+    let bob = Account::new("bob".to_string());
+    let miner = Account::new("miner".to_string());
+    let mut params = ChainParams::regtest();
+    params.premine = vec![(alice.public_key, 5)];
+    let mut blockchain = Blockchain::new(params);
+
+    let unsigned = Transaction::new(bob.public_key, alice.public_key, 5, 0, 0);
+    assert_eq!(
+        blockchain.add_transaction(unsigned.clone()),
+        Err(TransactionError::MissingSignature)
+    );
+    assert!(blockchain.mempool.is_empty());
+
+    let block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        vec![unsigned],
+    );
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::InvalidTransaction(
+            1,
+            TransactionError::MissingSignature
+        ))
+    );
+
+    let mut forged = transfer(&alice, &bob, 5, 0);
+    forged.amount = 50;
+    let block = child(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        vec![forged],
+    );
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::InvalidTransaction(
+            1,
+            TransactionError::InvalidSignature
+        ))
+    );
+    assert_eq!(blockchain.chain.len(), 1);
+
+    blockchain
+        .add_transaction(transfer(&alice, &bob, 5, 0))
+        .unwrap();
+    assert_eq!(blockchain.mempool.len(), 1);
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use common::{child_at, temp_dir, transfer, unlocked};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::BlockHeader;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::disk_store::DiskStore;
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::snapshot::{Snapshot, SnapshotError};
+use rust_blockchain::chain::store::MemoryStore;
+
+// Chain of six blocks where `payee` gets one unit in each of blocks 3 to 6
+fn source_chain(miner: &Account, payee: &Account) -> Blockchain {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    for i in 0..6 {
+        let data = if i >= 2 {
+            vec![transfer(miner, payee, 1, i - 2)]
+        } else {
+            vec![]
+        };
+        let block = child_at(&blockchain, *blockchain.tip_hash(), miner, data, 10 + i);
+        blockchain.add_block(block).unwrap();
+    }
+    blockchain
+}
+
+fn headers_to(blockchain: &Blockchain, height: u64) -> Vec<BlockHeader> {
+    (0..=height)
+        .map(|h| blockchain.header_at(h).unwrap().clone())
+        .collect()
+}
+
+#[test]
+fn snapshots_round_trip_through_a_checksummed_file() {
+    let miner = Account::new("miner".to_string());
+    let payee = Account::new("payee".to_string());
+    let source = source_chain(&miner, &payee);
+
+    let snapshot = source.snapshot(4).unwrap();
+    assert_eq!(snapshot.accounts()[&payee.public_key].balance, 2);
+    assert_eq!(
+        source.snapshot(6).unwrap().accounts()[&payee.public_key].balance,
+        4
+    );
+    assert!(matches!(
+        source.snapshot(7),
+        Err(SnapshotError::UnavailableHeight(7))
+    ));
+
+    let dir = temp_dir("snapshot-file");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("state.snap");
+    snapshot.save(&path).unwrap();
+    assert_eq!(Snapshot::load(&path).unwrap(), snapshot);
+
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[10] ^= 1;
+    assert!(matches!(
+        Snapshot::from_bytes(&bytes),
+        Err(SnapshotError::BadChecksum)
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bootstrap_from_a_trusted_snapshot() {
+    let miner = Account::new("miner".to_string());
+    let payee = Account::new("payee".to_string());
+    let source = source_chain(&miner, &payee);
+    let snapshot = source.snapshot(4).unwrap();
+    let headers = headers_to(&source, 4);
+
+    let mut fresh = Blockchain::from_snapshot(
+        ChainParams::regtest(),
+        Box::new(MemoryStore::default()),
+        &snapshot,
+        &headers,
+        Some(snapshot.hash()),
+    )
+    .unwrap();
+    assert_eq!(fresh.height(), 4);
+    assert_eq!(fresh.base_height(), 4);
+    assert_eq!(fresh.tip_header(), &headers[4]);
+    // Only the header of the snapshot block is known
+    assert_eq!(fresh.tip().unwrap(), None);
+    assert_eq!(fresh.balance(&payee.public_key).unwrap(), 2);
+    assert_eq!(fresh.validate_chain(), Ok(()));
+
+    for height in 5..=6 {
+        fresh
+            .add_block(source.block_at(height).unwrap().unwrap())
+            .unwrap();
+    }
+    assert_eq!(fresh.tip_hash(), source.tip_hash());
+    assert_eq!(
+        fresh.tip().unwrap().unwrap(),
+        source.tip().unwrap().unwrap()
+    );
+    assert_eq!(fresh.balance(&payee.public_key).unwrap(), 4);
+    assert_eq!(fresh.snapshot(6).unwrap(), source.snapshot(6).unwrap());
+    assert!(fresh.snapshot(3).is_err());
+    assert_eq!(fresh.validate_chain(), Ok(()));
+
+    // No undo data exists below the snapshot
+    let mut parent = fresh.chain[3];
+    let mut result = Ok(());
+    for i in 0..4 {
+        let block = child_at(&fresh, parent, &payee, vec![], 40 + i);
+        parent = block.hash();
+        result = fresh.add_block(block);
+    }
+    assert_eq!(result, Err(BlockError::ReorgTooDeep { fork: 3, min: 4 }));
+    assert_eq!(fresh.tip_hash(), source.tip_hash());
+}
+
+#[test]
+fn bootstrap_rejects_bad_snapshots_and_headers() {
+    let miner = Account::new("miner".to_string());
+    let payee = Account::new("payee".to_string());
+    let source = source_chain(&miner, &payee);
+    let snapshot = source.snapshot(4).unwrap();
+    let headers = headers_to(&source, 4);
+    let bootstrap = |snapshot: &Snapshot, headers: &[BlockHeader], trusted| {
+        Blockchain::from_snapshot(
+            ChainParams::regtest(),
+            Box::new(MemoryStore::default()),
+            snapshot,
+            headers,
+            trusted,
+        )
+        .err()
+        .unwrap()
+    };
+
+    assert!(matches!(
+        bootstrap(&snapshot, &headers, Some([1; 32])),
+        SnapshotError::HashMismatch { .. }
+    ));
+    // Valid headers and a matching state root aren't enough without a hash
+    // someone vouches for
+    assert!(matches!(
+        bootstrap(&snapshot, &headers, None),
+        SnapshotError::Untrusted
+    ));
+
+    let mut tampered = snapshot.clone();
+    tampered.entries[0].state.balance += 1;
+    assert!(matches!(
+        bootstrap(&tampered, &headers, Some(tampered.hash())),
+        SnapshotError::StateRootMismatch
+    ));
+
+    assert!(matches!(
+        bootstrap(&snapshot, &headers[..4], Some(snapshot.hash())),
+        SnapshotError::InvalidHeaders(4)
+    ));
+
+    // A changed header no longer meets its target or links to the next one
+    let mut forged = headers.clone();
+    forged[2].timestamp += 1;
+    let mut moved = snapshot.clone();
+    moved.block_hash = forged[4].hash();
+    assert!(matches!(
+        bootstrap(&moved, &forged, Some(moved.hash())),
+        SnapshotError::InvalidHeader { height: 2 | 3, .. }
+    ));
+}
+
+#[test]
+fn bootstrap_on_disk_survives_a_restart() {
+    let miner = Account::new("miner".to_string());
+    let payee = Account::new("payee".to_string());
+    let source = source_chain(&miner, &payee);
+    let snapshot = source.snapshot(4).unwrap();
+    let params = ChainParams {
+        snapshots: vec![(4, snapshot.hash())],
+        ..ChainParams::regtest()
+    };
+
+    let dir = temp_dir("snapshot-disk");
+    {
+        let store = DiskStore::open(&dir, params.magic).unwrap();
+        Blockchain::from_snapshot(
+            params.clone(),
+            Box::new(store),
+            &snapshot,
+            &headers_to(&source, 4),
+            None,
+        )
+        .unwrap();
+    }
+
+    let reopened = unlocked(|| Blockchain::open(params.clone(), &dir)).unwrap();
+    assert_eq!(reopened.base_height(), 4);
+    assert_eq!(reopened.tip().unwrap(), None);
+    assert_eq!(reopened.balance(&payee.public_key).unwrap(), 2);
+    assert_eq!(reopened.validate_chain(), Ok(()));
+    drop(reopened);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use std::collections::{BTreeMap, HashMap};
+
+use common::{child_at, solve, transfer};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::state::AccountState;
+use rust_blockchain::chain::state_tree::{StateTree, leaf_hash, path};
+use sha2::{Digest, Sha256};
+
+fn key(i: u64) -> [u8; 33] {
+    let mut key = [2; 33];
+    key[1..9].copy_from_slice(&i.to_le_bytes());
+    key
+}
+
+fn state(balance: u64, nonce: u64) -> AccountState {
+    AccountState { balance, nonce }
+}
+
+// Root computed from scratch over every leaf, without any cached nodes
+fn expected_root(accounts: &BTreeMap<[u8; 33], AccountState>) -> [u8; 32] {
+    fn subtree(leaves: &[([u8; 32], [u8; 32])], depth: usize) -> [u8; 32] {
+        match leaves {
+            [] => [0; 32],
+            [(_, leaf)] => *leaf,
+            _ => {
+                let split =
+                    leaves.partition_point(|(path, _)| path[depth / 8] >> (7 - depth % 8) & 1 == 0);
+                let mut hasher = Sha256::new();
+                hasher.update([0x01]);
+                hasher.update(subtree(&leaves[..split], depth + 1));
+                hasher.update(subtree(&leaves[split..], depth + 1));
+                hasher.finalize().into()
+            }
+        }
+    }
+
+    let mut leaves: Vec<_> = accounts
+        .iter()
+        .map(|(account, state)| (path(account), leaf_hash(account, state)))
+        .collect();
+    leaves.sort_unstable();
+    subtree(&leaves, 0)
+}
+
+#[test]
+fn root_follows_inserts_and_removals() {
+    let mut tree = StateTree::default();
+    let mut accounts = BTreeMap::new();
+    assert_eq!(tree.root(), [0; 32]);
+
+    for i in 0..64 {
+        let state = state(i, 0);
+        tree.insert(&key(i), &state);
+        accounts.insert(key(i), state);
+        assert_eq!(tree.root(), expected_root(&accounts));
+    }
+
+    // Updating an existing account only changes its own leaf
+    let state = state(1000, 3);
+    tree.insert(&key(7), &state);
+    accounts.insert(key(7), state);
+    assert_eq!(tree.root(), expected_root(&accounts));
+
+    for i in (0..64).step_by(3) {
+        tree.remove(&key(i));
+        accounts.remove(&key(i));
+        assert_eq!(tree.root(), expected_root(&accounts));
+    }
+    tree.remove(&key(1000));
+    assert_eq!(tree.root(), expected_root(&accounts));
+
+    let rebuilt = StateTree::new(accounts.clone());
+    assert_eq!(rebuilt.root(), tree.root());
+
+    for i in 0..64 {
+        tree.remove(&key(i));
+    }
+    assert!(tree.is_empty());
+    assert_eq!(tree.root(), [0; 32]);
+}
+
+#[test]
+fn root_after_leaves_the_tree_untouched() {
+    let accounts: BTreeMap<_, _> = (0..32).map(|i| (key(i), state(i, i))).collect();
+    let tree = StateTree::new(accounts.clone());
+    let root = tree.root();
+
+    let update: HashMap<_, _> = [
+        (key(3), state(0, 4)),
+        (key(100), state(50, 0)),
+        (key(101), state(60, 0)),
+    ]
+    .into_iter()
+    .collect();
+    let mut after = accounts.clone();
+    after.extend(update.clone());
+
+    assert_eq!(tree.root_after(&update), expected_root(&after));
+    assert_eq!(tree.root_after(&HashMap::new()), root);
+    assert_eq!(tree.root(), root);
+    assert_eq!(StateTree::default().root_after(&update), {
+        let only: BTreeMap<_, _> = update.into_iter().collect();
+        expected_root(&only)
+    });
+}
+
+#[test]
+fn account_proofs_verify_against_the_tip() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let miner = Account::new("miner".to_string());
+    let accounts: Vec<_> = (0..20)
+        .map(|i| Account::new(format!("account {i}")))
+        .collect();
+
+    let block = child_at(&blockchain, blockchain.chain[0], &miner, vec![], 1);
+    let mut parent = block.hash();
+    blockchain.add_block(block).unwrap();
+    for (i, account) in accounts.iter().enumerate().take(15) {
+        let i = i as u64;
+        let payment = transfer(&miner, account, 1 + i, i);
+        let block = child_at(&blockchain, parent, &miner, vec![payment], 2 + i);
+        parent = block.hash();
+        blockchain.add_block(block).unwrap();
+    }
+
+    let tip = blockchain.tip_header().clone();
+    for (i, account) in accounts.iter().enumerate() {
+        let proof = blockchain.account_proof(&account.public_key);
+        assert!(proof.verify(&tip));
+
+        if i < 15 {
+            assert_eq!(proof.balance(), 1 + i as u64);
+            let mut forged = proof.clone();
+            forged.state.as_mut().unwrap().balance += 1;
+            assert!(!forged.verify(&tip));
+            let mut forged = proof.clone();
+            forged.state = None;
+            assert!(!forged.verify(&tip));
+        } else {
+            // Proof of absence
+            assert_eq!(proof.state, None);
+            let mut forged = proof.clone();
+            forged.state = Some(AccountState::default());
+            forged.other = None;
+            assert!(!forged.verify(&tip));
+        }
+    }
+
+    let proof = blockchain.account_proof(&miner.public_key);
+    assert!(proof.verify(&tip));
+    assert_eq!(proof.nonce(), 15);
+    assert!(!proof.verify(blockchain.header_at(3).unwrap()));
+}
+
+#[test]
+fn block_with_wrong_state_root_is_rejected() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let miner = Account::new("miner".to_string());
+
+    let mut block = child_at(&blockchain, blockchain.chain[0], &miner, vec![], 1);
+    block.state_root[0] ^= 1;
+    let block = solve(block);
+    assert_eq!(
+        blockchain.add_block(block.clone()),
+        Err(BlockError::InvalidStateRoot)
+    );
+    assert!(blockchain.tree.get(&block.hash()).unwrap().invalid);
+    assert_eq!(blockchain.chain.len(), 1);
+
+    // The rejected block left the tree as it was, so a template still fits
+    blockchain.mine_block(miner.public_key).unwrap();
+    assert_eq!(blockchain.chain.len(), 2);
+    assert!(
+        blockchain
+            .account_proof(&miner.public_key)
+            .verify(blockchain.tip_header())
+    );
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+}
@@ -0,0 +1,230 @@
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use common::{child_at, temp_dir, transfer, unlocked};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::{Block, BlockHeader};
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain, ChainError};
+use rust_blockchain::chain::disk_store::DiskStore;
+use rust_blockchain::chain::index::TxLocation;
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::state::AccountState;
+use rust_blockchain::chain::storage::StorageError;
+use rust_blockchain::chain::store::{ChainStore, MemoryStore, WriteBatch};
+use rust_blockchain::chain::transaction::TransactionError;
+use rust_blockchain::chain::undo::BlockUndo;
+
+// Memory store whose reads start failing once `reads_left` runs out. None
+// means it never does.
+struct FlakyStore {
+    inner: MemoryStore,
+    reads_left: Rc<Cell<Option<usize>>>,
+}
+
+impl FlakyStore {
+    fn check(&self) -> Result<(), StorageError> {
+        match self.reads_left.get() {
+            Some(0) => Err(StorageError::Database("disk unplugged".to_string())),
+            Some(n) => {
+                self.reads_left.set(Some(n - 1));
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl ChainStore for FlakyStore {
+    fn get_block(&self, hash: &[u8; 32]) -> Result<Option<Block>, StorageError> {
+        self.check()?;
+        self.inner.get_block(hash)
+    }
+
+    fn get_header(&self, hash: &[u8; 32]) -> Result<Option<BlockHeader>, StorageError> {
+        self.check()?;
+        self.inner.get_header(hash)
+    }
+
+    fn headers(&self) -> Result<Vec<BlockHeader>, StorageError> {
+        self.check()?;
+        self.inner.headers()
+    }
+
+    fn get_account(&self, account: &[u8; 33]) -> Result<Option<AccountState>, StorageError> {
+        self.check()?;
+        self.inner.get_account(account)
+    }
+
+    fn accounts(&self) -> Result<Vec<([u8; 33], AccountState)>, StorageError> {
+        self.check()?;
+        self.inner.accounts()
+    }
+
+    fn get_undo(&self, hash: &[u8; 32]) -> Result<Option<BlockUndo>, StorageError> {
+        self.check()?;
+        self.inner.get_undo(hash)
+    }
+
+    fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        self.check()?;
+        self.inner.get_meta(key)
+    }
+
+    fn get_block_height(&self, hash: &[u8; 32]) -> Result<Option<u64>, StorageError> {
+        self.check()?;
+        self.inner.get_block_height(hash)
+    }
+
+    fn get_tx_location(&self, txid: &[u8; 32]) -> Result<Option<TxLocation>, StorageError> {
+        self.check()?;
+        self.inner.get_tx_location(txid)
+    }
+
+    fn address_txs(&self, account: &[u8; 33]) -> Result<Vec<([u8; 32], TxLocation)>, StorageError> {
+        self.check()?;
+        self.inner.address_txs(account)
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StorageError> {
+        self.inner.write(batch)
+    }
+}
+
+#[test]
+fn read_errors_are_returned_instead_of_panicking() {
+    let reads_left = Rc::new(Cell::new(None));
+    let store = FlakyStore {
+        inner: MemoryStore::default(),
+        reads_left: reads_left.clone(),
+    };
+    let mut blockchain = Blockchain::with_store(ChainParams::regtest(), Box::new(store)).unwrap();
+    let miner = Account::new("miner".to_string());
+    let other = Account::new("other".to_string());
+
+    let block = child_at(&blockchain, blockchain.chain[0], &miner, vec![], 1);
+    blockchain.add_block(block).unwrap();
+
+    reads_left.set(Some(0));
+    assert!(blockchain.balance(&miner.public_key).is_err());
+    assert!(blockchain.tip().is_err());
+    assert!(blockchain.get_block(&blockchain.chain[1]).is_err());
+    assert!(matches!(
+        blockchain.add_transaction(transfer(&miner, &other, 5, 0)),
+        Err(TransactionError::Storage(_))
+    ));
+    assert!(matches!(
+        blockchain.mine_block(miner.public_key),
+        Err(BlockError::Storage(_))
+    ));
+    assert!(matches!(
+        blockchain.validate_chain(),
+        Err(ChainError::Storage(_))
+    ));
+
+    reads_left.set(None);
+    blockchain
+        .add_transaction(transfer(&miner, &other, 5, 0))
+        .unwrap();
+    blockchain.mine_block(miner.public_key).unwrap();
+    assert_eq!(blockchain.balance(&other.public_key).unwrap(), 5);
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+}
+
+#[test]
+fn memory_and_disk_stores_agree() {
+    let dir = temp_dir("store-agree");
+    let miner = Account::new("miner".to_string());
+    let other = Account::new("other".to_string());
+
+    let mut memory = Blockchain::new(ChainParams::regtest());
+    let mut disk = Blockchain::open(ChainParams::regtest(), &dir).unwrap();
+    for i in 0..3 {
+        let data = if i == 1 {
+            vec![transfer(&miner, &other, 7, 0)]
+        } else {
+            vec![]
+        };
+        let block = child_at(&memory, *memory.tip_hash(), &miner, data, 10 + i);
+        memory.add_block(block.clone()).unwrap();
+        disk.add_block(block).unwrap();
+    }
+
+    assert_eq!(memory.tip_hash(), disk.tip_hash());
+    for account in [&miner, &other] {
+        assert_eq!(
+            memory.account(&account.public_key).unwrap(),
+            disk.account(&account.public_key).unwrap()
+        );
+    }
+    drop(disk);
+
+    let reopened = unlocked(|| Blockchain::open(ChainParams::regtest(), &dir)).unwrap();
+    assert_eq!(reopened.tip_hash(), memory.tip_hash());
+    assert_eq!(reopened.balance(&other.public_key).unwrap(), 7);
+    assert_eq!(reopened.validate_chain(), Ok(()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn open_database_is_locked() {
+    let dir = temp_dir("store-locked");
+    let magic = ChainParams::regtest().magic;
+
+    let store = DiskStore::open(&dir, magic).unwrap();
+    assert!(matches!(
+        DiskStore::open(&dir, magic),
+        Err(StorageError::Locked)
+    ));
+
+    // Closing the store releases the lock
+    drop(store);
+    unlocked(|| DiskStore::open(&dir, magic)).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn read_errors_while_connecting_do_not_invalidate() {
+    let miner = Account::new("miner".to_string());
+    let other = Account::new("other".to_string());
+
+    // Fail the store after each number of reads in turn, so every read on
+    // the way to connecting the block gets its turn to break
+    for allowed in 0.. {
+        let reads_left = Rc::new(Cell::new(None));
+        let store = FlakyStore {
+            inner: MemoryStore::default(),
+            reads_left: reads_left.clone(),
+        };
+        let mut blockchain =
+            Blockchain::with_store(ChainParams::regtest(), Box::new(store)).unwrap();
+        let block = child_at(
+            &blockchain,
+            blockchain.chain[0],
+            &miner,
+            vec![transfer(&miner, &other, 5, 0)],
+            1,
+        );
+        let hash = block.hash();
+
+        reads_left.set(Some(allowed));
+        let result = blockchain.add_block(block.clone());
+        reads_left.set(None);
+        if result.is_ok() {
+            assert!(allowed > 0);
+            break;
+        }
+        assert!(matches!(result, Err(BlockError::Storage(_))));
+
+        // The block wasn't flagged, so once the store recovers it connects
+        // along with a child built on it
+        if !blockchain.tree.contains(&hash) {
+            blockchain.add_block(block.clone()).unwrap();
+        }
+        let grandchild = child_at(&blockchain, hash, &miner, vec![], 2);
+        blockchain.add_block(grandchild.clone()).unwrap();
+        assert_eq!(*blockchain.tip_hash(), grandchild.hash());
+        assert_eq!(blockchain.balance(&other.public_key).unwrap(), 5);
+    }
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::solve;
+use rust_blockchain::chain::block::Block;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::target::{U256, hash_meets_target};
+
+fn from_u128(value: u128) -> U256 {
+    let mut bytes = [0; 32];
+    bytes[16..].copy_from_slice(&value.to_be_bytes());
+    U256::from_be_bytes(bytes)
+}
+
+fn to_u128(value: U256) -> u128 {
+    u128::from_be_bytes(value.to_be_bytes()[16..].try_into().unwrap())
+}
+
+#[test]
+fn compact_bits_round_trip() {
+    let target = U256::from_compact(0x1d00ffff).unwrap();
+    assert_eq!(
+        target.to_string(),
+        "00000000ffff0000000000000000000000000000000000000000000000000000"
+    );
+    assert_eq!(target.to_compact(), 0x1d00ffff);
+
+    for bits in [
+        0x1b0404cb, 0x207fffff, 0x2000ffff, 0x1f00ffff, 0x03123456, 0x01120000,
+    ] {
+        assert_eq!(U256::from_compact(bits).unwrap().to_compact(), bits);
+    }
+
+    // The top mantissa bit is a sign, so it moves into the next byte
+    assert_eq!(U256::from_u64(0x80).to_compact(), 0x02008000);
+
+    // Negative and overflowing encodings are invalid
+    assert_eq!(U256::from_compact(0x04923456), None);
+    assert_eq!(U256::from_compact(0x2300ffff), None);
+    assert_eq!(U256::from_compact(0x1d000000), Some(U256::ZERO));
+}
+
+#[test]
+fn arithmetic_matches_u128() {
+    let mut seed = 12345u64;
+    let mut next = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    for _ in 0..2000 {
+        let a = ((next() as u128) << 64) | next() as u128;
+        let b = ((((next() as u128) << 64) | next() as u128) >> (next() % 120)).max(1);
+        let shift = (next() % 64) as u32;
+        let small = next() | 1;
+        let (wide_a, wide_b) = (from_u128(a), from_u128(b));
+
+        assert_eq!(to_u128(wide_a / wide_b), a / b);
+        assert_eq!(to_u128(wide_a >> shift), a >> shift);
+        assert_eq!(to_u128((wide_a >> 64) << shift), (a >> 64) << shift);
+        assert_eq!(to_u128(wide_a.div_u64(small)), a / small as u128);
+        assert_eq!(
+            wide_a.checked_sub(wide_a.div_u64(3)).map(to_u128),
+            Some(a - a / 3)
+        );
+        assert_eq!(
+            (wide_a >> 64).checked_mul_u64(small).map(to_u128),
+            Some((a >> 64) * small as u128)
+        );
+        assert_eq!(wide_a < wide_b, a < b);
+    }
+
+    assert_eq!((U256::ONE << 255) >> 255, U256::ONE);
+    assert_eq!((U256::ONE << 200) / (U256::ONE << 100), U256::ONE << 100);
+    assert_eq!(U256::MAX.checked_add(U256::ONE), None);
+    assert_eq!(U256::ZERO.checked_sub(U256::ONE), None);
+}
+
+#[test]
+fn hashes_are_compared_against_the_full_target() {
+    let bits = 0x1d00ffff;
+    let mut hash = [0; 32];
+    hash[4] = 0xff;
+    hash[5] = 0xff;
+    assert!(hash_meets_target(&hash, bits));
+    hash[6] = 1;
+    assert!(!hash_meets_target(&hash, bits));
+    assert!(!hash_meets_target(&[0; 32], 0x04923456));
+}
+
+#[test]
+fn blocks_must_claim_the_chain_target() {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let genesis = blockchain.tip().unwrap().unwrap().hash();
+    assert_eq!(blockchain.bits(), 0x207fffff);
+    assert_eq!(blockchain.tip().unwrap().unwrap().bits, 0x207fffff);
+
+    // An easier target than the chain's is refused even if the hash meets it
+    let mut block = Block::new(1, genesis, 0x2100ffff, vec![]);
+    block.timestamp = blockchain.tip().unwrap().unwrap().timestamp + 1;
+    let block = solve(block);
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::InvalidBits {
+            expected: 0x207fffff,
+            found: 0x2100ffff
+        })
+    );
+
+    blockchain.mine_block([2; 33]).unwrap();
+    assert!(blockchain.tip().unwrap().unwrap().meets_target());
+    assert_eq!(blockchain.tip().unwrap().unwrap().bits, 0x207fffff);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::{solve, transfer_with_fee};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::params::ChainParams;
+
+fn funded(account: &Account, balance: u64) -> Blockchain {
+    let mut params = ChainParams::regtest();
+    params.premine = vec![(account.public_key, balance)];
+    Blockchain::new(params)
+}
+
+#[test]
+fn template_is_mined_outside_the_node() {
+    let alice = Account::new("alice".to_string());
+    let miner = Account::new("miner".to_string());
+    let mut blockchain = funded(&alice, 100);
+    blockchain
+        .add_transaction(transfer_with_fee(&alice, &miner, 10, 2, 0))
+        .unwrap();
+
+    let template = blockchain.block_template(miner.public_key).unwrap();
+    assert_eq!(template.index, 1);
+    assert_eq!(
+        template.prev_hash,
+        blockchain.tip().unwrap().unwrap().hash()
+    );
+    assert_eq!(template.bits, blockchain.bits());
+    assert_eq!(template.data.len(), 2);
+    assert!(template.data[0].is_coinbase());
+    assert_eq!(
+        template.data[0].amount,
+        blockchain.params.subsidy.subsidy_at(1) + 2
+    );
+    assert!(template.verify_merkle_root());
+
+    // Building a template changes nothing until a solution comes back
+    assert_eq!(blockchain.chain.len(), 1);
+    assert_eq!(blockchain.mempool.len(), 1);
+
+    let hash = blockchain.submit_block(solve(template)).unwrap();
+    assert_eq!(blockchain.tip().unwrap().unwrap().hash(), hash);
+    assert_eq!(blockchain.mempool.len(), 0);
+    assert_eq!(
+        blockchain.balance(&miner.public_key).unwrap(),
+        blockchain.params.subsidy.subsidy_at(1) + 12
+    );
+}
+
+#[test]
+fn stale_template_only_forks_the_chain() {
+    let alice = Account::new("alice".to_string());
+    let miner = Account::new("miner".to_string());
+    let mut blockchain = funded(&alice, 100);
+
+    let stale = blockchain.block_template(miner.public_key).unwrap();
+    let tip = blockchain.mine_block(alice.public_key).unwrap().unwrap();
+
+    // A block solved for an old tip is kept as a side branch with no more
+    // work than the active one
+    let stale = solve(stale);
+    blockchain.submit_block(stale.clone()).unwrap();
+    assert_eq!(blockchain.tip().unwrap().unwrap().hash(), tip);
+    assert_eq!(
+        blockchain.submit_block(stale),
+        Err(BlockError::AlreadyKnown)
+    );
+
+    let fresh = blockchain.block_template(miner.public_key).unwrap();
+    assert_eq!(fresh.index, 2);
+    assert_eq!(fresh.prev_hash, blockchain.tip().unwrap().unwrap().hash());
+    blockchain.submit_block(solve(fresh)).unwrap();
+    assert_eq!(blockchain.height(), 2);
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::{child, solve};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::block::Block;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain};
+use rust_blockchain::chain::clock::MockClock;
+use rust_blockchain::chain::params::ChainParams;
+
+// A chain whose clock stands at the genesis timestamp
+fn chain() -> (Blockchain, MockClock, u64) {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    let genesis = blockchain.tip().unwrap().unwrap().timestamp;
+    let clock = MockClock::new(genesis);
+    blockchain.clock = Box::new(clock.clone());
+    (blockchain, clock, genesis)
+}
+
+// Child of `parent` stamped `timestamp`
+fn stamped(blockchain: &Blockchain, parent: &Block, miner: &Account, timestamp: u64) -> Block {
+    let mut block = child(blockchain, parent, miner, vec![]);
+    block.timestamp = timestamp;
+    solve(block)
+}
+
+#[test]
+fn timestamp_must_pass_the_median_of_the_last_eleven() {
+    let (mut blockchain, _, genesis) = chain();
+    let miner = Account::new("miner".to_string());
+
+    for offset in 1..=11 {
+        let block = stamped(
+            &blockchain,
+            &blockchain.tip().unwrap().unwrap(),
+            &miner,
+            genesis + offset,
+        );
+        blockchain.add_block(block).unwrap();
+    }
+    let tip = *blockchain.tip_hash();
+    assert_eq!(blockchain.median_time_past(&tip).unwrap(), genesis + 6);
+
+    let block = stamped(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        genesis + 6,
+    );
+    assert_eq!(
+        blockchain.add_block(block),
+        Err(BlockError::TimestampTooOld {
+            median: genesis + 6,
+            found: genesis + 6
+        })
+    );
+
+    // Earlier than its parent is fine as long as it's past the median
+    let block = stamped(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        genesis + 7,
+    );
+    blockchain.add_block(block).unwrap();
+    assert_eq!(blockchain.height(), 12);
+}
+
+#[test]
+fn timestamp_may_only_run_slightly_ahead_of_the_clock() {
+    let (mut blockchain, clock, genesis) = chain();
+    let miner = Account::new("miner".to_string());
+    let drift = blockchain.max_future_drift;
+
+    let block = stamped(
+        &blockchain,
+        &blockchain.tip().unwrap().unwrap(),
+        &miner,
+        genesis + drift + 1,
+    );
+    assert_eq!(
+        blockchain.add_block(block.clone()),
+        Err(BlockError::TimestampTooFarAhead {
+            max: genesis + drift,
+            found: genesis + drift + 1
+        })
+    );
+
+    // The same block is accepted once the clock catches up
+    clock.advance(1);
+    blockchain.add_block(block).unwrap();
+    assert_eq!(blockchain.height(), 1);
+}
+
+#[test]
+fn template_never_goes_back_past_the_median() {
+    let (mut blockchain, clock, genesis) = chain();
+    let miner = Account::new("miner".to_string());
+
+    for offset in 1..=5 {
+        let block = stamped(
+            &blockchain,
+            &blockchain.tip().unwrap().unwrap(),
+            &miner,
+            genesis + offset * 100,
+        );
+        blockchain.add_block(block).unwrap();
+    }
+
+    // A clock running behind the chain still yields a valid block
+    clock.set(genesis);
+    let tip = *blockchain.tip_hash();
+    let template = blockchain.block_template(miner.public_key).unwrap();
+    assert_eq!(
+        template.timestamp,
+        blockchain.median_time_past(&tip).unwrap() + 1
+    );
+    blockchain.mine_block(miner.public_key).unwrap();
+    assert_eq!(blockchain.height(), 6);
+
+    clock.set(genesis + 10_000);
+    let template = blockchain.block_template(miner.public_key).unwrap();
+    assert_eq!(template.timestamp, genesis + 10_000);
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+}
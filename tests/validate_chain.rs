@@ -0,0 +1,98 @@
+mod common;
+
+use common::{child, transfer};
+use rust_blockchain::chain::account::Account;
+use rust_blockchain::chain::blockchain::{BlockError, Blockchain, ChainError};
+use rust_blockchain::chain::params::ChainParams;
+use rust_blockchain::chain::state::AccountState;
+
+// Four blocks on genesis, the third paying `recipient`
+fn chain(miner: &Account, recipient: &Account) -> Blockchain {
+    let mut blockchain = Blockchain::new(ChainParams::regtest());
+    for height in 1..=4 {
+        let data = if height == 3 {
+            vec![transfer(miner, recipient, 5, 0)]
+        } else {
+            vec![]
+        };
+        let block = child(
+            &blockchain,
+            &blockchain.tip().unwrap().unwrap(),
+            miner,
+            data,
+        );
+        blockchain.add_block(block).unwrap();
+    }
+    blockchain
+}
+
+#[test]
+fn exported_chain_is_replayed_from_genesis() {
+    let miner = Account::new("miner".to_string());
+    let alice = Account::new("alice".to_string());
+    let blockchain = chain(&miner, &alice);
+    assert_eq!(blockchain.validate_chain(), Ok(()));
+
+    let blocks = Blockchain::decode_blocks(&blockchain.encode_blocks().unwrap()).unwrap();
+    assert_eq!(blocks.len(), 5);
+    let copy = Blockchain::from_blocks(ChainParams::regtest(), blocks).unwrap();
+    assert_eq!(copy.tip_hash(), blockchain.tip_hash());
+    assert_eq!(copy.validate_chain(), Ok(()));
+    assert_eq!(
+        copy.store.accounts().unwrap().len(),
+        blockchain.store.accounts().unwrap().len()
+    );
+    assert_eq!(copy.balance(&alice.public_key).unwrap(), 5);
+}
+
+#[test]
+fn first_invalid_block_is_reported() {
+    let miner = Account::new("miner".to_string());
+    let alice = Account::new("alice".to_string());
+    let blocks =
+        Blockchain::decode_blocks(&chain(&miner, &alice).encode_blocks().unwrap()).unwrap();
+
+    assert_eq!(
+        Blockchain::from_blocks(ChainParams::regtest(), vec![]).err(),
+        Some(ChainError::Empty)
+    );
+
+    let mut tampered = blocks.clone();
+    tampered[3].data[1].amount = 6;
+    assert_eq!(
+        Blockchain::from_blocks(ChainParams::regtest(), tampered).err(),
+        Some(ChainError::InvalidBlock {
+            height: 3,
+            error: BlockError::InvalidMerkleRoot
+        })
+    );
+
+    let mut reordered = blocks;
+    reordered.swap(1, 2);
+    assert_eq!(
+        Blockchain::from_blocks(ChainParams::regtest(), reordered).err(),
+        Some(ChainError::InvalidBlock {
+            height: 1,
+            error: BlockError::InvalidPrevHash
+        })
+    );
+}
+
+#[test]
+fn state_that_drifted_from_the_blocks_is_caught() {
+    let miner = Account::new("miner".to_string());
+    let alice = Account::new("alice".to_string());
+    let mut blockchain = chain(&miner, &alice);
+
+    blockchain
+        .store
+        .put_account(
+            alice.public_key,
+            AccountState {
+                balance: 1,
+                nonce: 9,
+            },
+        )
+        .unwrap();
+    assert_eq!(blockchain.validate_chain(), Err(ChainError::StateMismatch));
+}